@@ -1,18 +1,53 @@
-mod univsrg;
-
 use std::path::PathBuf;
 
-use clap::{ArgAction, Parser};
+use clap::{error::ErrorKind, ArgAction, Args, CommandFactory, Parser, Subcommand};
 
-use crate::univsrg::{
+use univsrg::univsrg::{
+    diff::{diff, DiffOptions},
+    info::{beatmap_info, BeatmapInfo},
+    judgement::HitWindows,
     osu::types::OszPath,
+    render::{render, RenderOptions},
+    simulate::{autoplay, parse_key_events, Simulator},
     traits::{AppendToUnivsrg, ToOsu},
+    transform::pipeline::Pipeline,
     types::Package,
+    validate::{validate, Severity},
+    viewer::Viewer,
 };
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Without a subcommand, convert.
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert beatmaps to another game.
+    Convert(ConvertArgs),
+    /// Check beatmaps for mistakes.
+    Validate(ValidateArgs),
+    /// Show metadata and statistics of beatmaps.
+    Info(InfoArgs),
+    /// Compare the beatmaps of two packages.
+    Diff(DiffArgs),
+    /// Draw beatmaps as preview images.
+    Render(RenderArgs),
+    /// Show beatmaps scrolling in the terminal.
+    View(ViewArgs),
+    /// Play beatmaps with autoplay or recorded key events, and show the results.
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args)]
+struct ConvertArgs {
     /// Input files.
     /// Support extensions include `.osz`.
     #[arg(action = ArgAction::Append)]
@@ -21,34 +56,436 @@ struct Args {
     /// Output file.
     /// Supported extensions include `.osz`.
     #[arg(short)]
+    output: Option<String>,
+
+    /// Transforms to run in order, e.g. `--transform mirror --transform rate=1.2`.
+    #[arg(short, long = "transform", action = ArgAction::Append)]
+    transforms: Vec<String>,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// Input files.
+    /// Support extensions include `.osz`.
+    #[arg(action = ArgAction::Append, required = true)]
+    inputs: Vec<String>,
+}
+
+#[derive(Args)]
+struct InfoArgs {
+    /// Input files.
+    /// Support extensions include `.osz`.
+    #[arg(action = ArgAction::Append, required = true)]
+    inputs: Vec<String>,
+
+    /// Print JSON instead.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    /// The old file.
+    /// Support extensions include `.osz`.
+    old: String,
+
+    /// The new file.
+    /// Support extensions include `.osz`.
+    new: String,
+
+    /// Times differing by up to this in ms are the same.
+    #[arg(long, default_value_t = 1)]
+    tolerance: i32,
+
+    /// Objects shifted by up to this in ms are moved rather than removed and added.
+    #[arg(long, default_value_t = 100)]
+    max_move: i32,
+}
+
+#[derive(Args)]
+struct RenderArgs {
+    /// Input files.
+    /// Support extensions include `.osz`.
+    #[arg(action = ArgAction::Append, required = true)]
+    inputs: Vec<String>,
+
+    /// Output folder, with an image for every beatmap.
+    #[arg(short, default_value = ".")]
     output: String,
+
+    /// Image type, `png` or `svg`.
+    #[arg(long, default_value = "png")]
+    format: String,
+
+    /// Height of every strip in pixels.
+    #[arg(long, default_value_t = 1200)]
+    strip_height: u32,
+
+    /// Pixels per second of music.
+    #[arg(long, default_value_t = 300.0)]
+    pixels_per_second: f32,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(Args)]
+struct ViewArgs {
+    /// Input files.
+    /// Support extensions include `.osz`.
+    #[arg(action = ArgAction::Append, required = true)]
+    inputs: Vec<String>,
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    /// Input files.
+    /// Support extensions include `.osz`.
+    #[arg(action = ArgAction::Append, required = true)]
+    inputs: Vec<String>,
+
+    /// Key events to play every beatmap with instead of autoplay,
+    /// in lines of `<offset> <column> <press|release>`.
+    #[arg(long)]
+    events: Option<String>,
+
+    /// Overall difficulty of osu! to use instead of the hit windows of the beatmaps.
+    #[arg(long)]
+    od: Option<f32>,
+
+    /// HP drain rate of osu! to use instead of that of the beatmaps.
+    #[arg(long)]
+    hp: Option<f32>,
+
+    /// Print JSON instead.
+    #[arg(long)]
+    json: bool,
+}
+
+fn load(inputs: &[String]) -> Package {
     let mut package = Package::new();
-    for path in &args.inputs {
+    for path in inputs {
         let path = PathBuf::from(path);
         let result = match path.extension().and_then(|it| it.to_str()) {
             Some("osz") => OszPath(path.clone()).append_to_univsrg(&mut package),
             _ => {
-                println!("Unsupported input type, skip.");
+                eprintln!("Unsupported input type, skip.");
                 continue;
             }
         };
         if result.is_err() {
-            println!("Failed to parse {}", path.to_string_lossy());
+            eprintln!("Failed to parse {}", path.to_string_lossy());
             continue;
         }
     }
+    package
+}
+
+/// Returns whether the output is written.
+fn convert(args: &ConvertArgs) -> bool {
+    // Optional only to allow subcommands without it.
+    let Some(output) = &args.output else {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "The output file (-o) is required.",
+            )
+            .exit();
+    };
+    let pipeline = match Pipeline::from_specs(&args.transforms) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    };
+    let mut package = load(&args.inputs);
 
-    let path = PathBuf::from(&args.output);
+    match pipeline.run(&mut package) {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return false;
+        }
+    }
+
+    let path = PathBuf::from(output);
     let result = match path.extension().and_then(|it| it.to_str()) {
         Some("osz") => package.to_osu(&path),
         _ => {
-            println!("Unsupported output type, abort.");
-            return;
+            eprintln!("Unsupported output type, abort.");
+            return false;
         }
     };
-    result.expect(&format!("Failed to compile {}", path.to_string_lossy()));
+    match result {
+        Ok(warnings) => {
+            for warning in warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to compile {}: {}", path.to_string_lossy(), e);
+            false
+        }
+    }
+}
+
+/// Returns whether there is no error.
+fn validate_inputs(args: &ValidateArgs) -> bool {
+    let package = load(&args.inputs);
+    let mut error_count = 0;
+    let mut warning_count = 0;
+    for (beatmap, diagnostics) in package.beatmaps.iter().zip(validate(&package)) {
+        println!("{}", beatmap.make_basename());
+        for diagnostic in &diagnostics {
+            println!("  {}", diagnostic);
+            match diagnostic.severity {
+                Severity::Error => error_count += 1,
+                Severity::Warning => warning_count += 1,
+            }
+        }
+    }
+    println!("{} errors, {} warnings.", error_count, warning_count);
+    error_count == 0
+}
+
+fn print_info(info: &BeatmapInfo) {
+    let text = |v: &Option<String>| v.clone().unwrap_or_default();
+    println!(
+        "{} - {} [{}] by {}",
+        text(&info.artist),
+        text(&info.title),
+        text(&info.version),
+        text(&info.creator)
+    );
+    if info.title_unicode.is_some() || info.artist_unicode.is_some() {
+        println!(
+            "  Unicode: {} - {}",
+            text(&info.artist_unicode),
+            text(&info.title_unicode)
+        );
+    }
+    if let Some(source) = &info.source {
+        println!("  Source: {}", source);
+    }
+    if !info.tags.is_empty() {
+        println!("  Tags: {}", info.tags.join(" "));
+    }
+    if let Some(key_count) = info.key_count {
+        println!("  Keys: {}K", key_count);
+    }
+    println!(
+        "  Length: {}:{:02}",
+        info.length / 60000,
+        info.length / 1000 % 60
+    );
+    println!(
+        "  Notes: {}, long notes: {}",
+        info.note_count, info.long_note_count
+    );
+    if let Some(bpm) = &info.bpm {
+        println!("  BPM: {} ({}-{})", bpm.main, bpm.min, bpm.max);
+    }
+    println!(
+        "  NPS: {:.2} average, {} peak",
+        info.nps.average, info.nps.peak
+    );
+    println!("  SV changes: {}", info.sv_count);
+    println!("  Star rating: {:.2}", info.star_rating);
+    if let Some(msd) = &info.msd {
+        println!(
+            "  MSD: {:.2} (stream {:.2}, jumpstream {:.2}, handstream {:.2}, stamina {:.2}, \
+            jackspeed {:.2}, chordjack {:.2}, technical {:.2})",
+            msd.overall,
+            msd.stream,
+            msd.jumpstream,
+            msd.handstream,
+            msd.stamina,
+            msd.jackspeed,
+            msd.chordjack,
+            msd.technical
+        );
+    }
+    for resource in &info.resources {
+        println!(
+            "  {}: {} ({} bytes)",
+            resource.kind, resource.path, resource.size
+        );
+    }
+}
+
+/// Returns whether there is no difference.
+fn diff_inputs(args: &DiffArgs) -> bool {
+    let old = load(std::slice::from_ref(&args.old));
+    let new = load(std::slice::from_ref(&args.new));
+    let options = DiffOptions {
+        tolerance: args.tolerance,
+        max_move: args.max_move,
+    };
+    // Pair beatmaps by version, or by order if the versions differ.
+    let mut unpaired = new.beatmaps.iter().collect::<Vec<_>>();
+    let mut pairs = vec![];
+    let mut removed = vec![];
+    for beatmap in &old.beatmaps {
+        match unpaired.iter().position(|v| v.version == beatmap.version) {
+            Some(idx) => pairs.push((beatmap, unpaired.remove(idx))),
+            None => removed.push(beatmap),
+        }
+    }
+    if removed.len() == 1 && unpaired.len() == 1 {
+        pairs.push((removed.remove(0), unpaired.remove(0)));
+    }
+
+    let mut is_same = removed.is_empty() && unpaired.is_empty();
+    for beatmap in removed {
+        println!("- {}", beatmap.make_basename());
+    }
+    for beatmap in &unpaired {
+        println!("+ {}", beatmap.make_basename());
+    }
+    for (old, new) in pairs {
+        let diff = diff(old, new, &options);
+        if diff.is_empty() {
+            continue;
+        }
+        is_same = false;
+        println!("{}", old.make_basename());
+        for line in diff.to_string().lines() {
+            println!("  {}", line);
+        }
+    }
+    is_same
+}
+
+fn render_inputs(args: &RenderArgs) {
+    let package = load(&args.inputs);
+    let options = RenderOptions {
+        strip_height: args.strip_height,
+        pixels_per_second: args.pixels_per_second,
+        ..Default::default()
+    };
+    for beatmap in &package.beatmaps {
+        let path = PathBuf::from(&args.output).join(format!(
+            "{}.{}",
+            beatmap.make_basename(),
+            args.format
+        ));
+        match render(beatmap, &options).and_then(|canvas| canvas.save(&path)) {
+            Ok(()) => println!("{}", path.to_string_lossy()),
+            Err(e) => println!("Failed to render {}: {}", path.to_string_lossy(), e),
+        }
+    }
+}
+
+/// Returns whether every beatmap is passed.
+fn simulate_inputs(args: &SimulateArgs) -> bool {
+    let events = match args
+        .events
+        .as_ref()
+        .map(|path| std::fs::read_to_string(path).and_then(|text| parse_key_events(&text)))
+    {
+        Some(Ok(events)) => Some(events),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            return false;
+        }
+        None => None,
+    };
+    let package = load(&args.inputs);
+    let mut results = vec![];
+    for beatmap in &package.beatmaps {
+        let mut simulator = Simulator::new(beatmap);
+        if let Some(od) = args.od {
+            simulator.hit_windows = HitWindows::from_osu_od(od);
+        }
+        if let Some(hp) = args.hp {
+            simulator.hp_difficulty = hp;
+        }
+        let result = match &events {
+            Some(events) => simulator.run(events),
+            None => simulator.run(&autoplay(beatmap)),
+        };
+        results.push((beatmap.make_basename(), result));
+    }
+
+    if args.json {
+        let results = results
+            .iter()
+            .map(|(name, result)| serde_json::json!({ "beatmap": name, "result": result }))
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&results).unwrap());
+    } else {
+        for (name, result) in &results {
+            let counts = &result.counts;
+            println!("{}", name);
+            println!(
+                "  Score: {}, accuracy: {:.2}%, max combo: {}",
+                result.score,
+                result.accuracy * 100.0,
+                result.max_combo
+            );
+            println!(
+                "  MAX {}, 300 {}, 200 {}, 100 {}, 50 {}, miss {}",
+                counts.perfect, counts.great, counts.good, counts.ok, counts.meh, counts.miss
+            );
+            match result.failed_at {
+                Some(offset) => println!("  Failed at {} ms", offset),
+                None => println!("  Passed with {:.0}% HP", result.hp * 100.0),
+            }
+        }
+    }
+    results.iter().all(|(_, result)| result.failed_at.is_none())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Convert(args)) => {
+            if !convert(args) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Validate(args)) => {
+            if !validate_inputs(args) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Info(args)) => {
+            let package = load(&args.inputs);
+            let infos = package
+                .beatmaps
+                .iter()
+                .map(beatmap_info)
+                .collect::<Vec<_>>();
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&infos).unwrap());
+            } else {
+                infos.iter().for_each(print_info);
+            }
+        }
+        Some(Command::Diff(args)) => {
+            if !diff_inputs(args) {
+                std::process::exit(1);
+            }
+        }
+        Some(Command::Render(args)) => render_inputs(args),
+        Some(Command::View(args)) => {
+            let package = load(&args.inputs);
+            if let Err(e) = Viewer::new(&package).run() {
+                println!("{}", e);
+            }
+        }
+        Some(Command::Simulate(args)) => {
+            if !simulate_inputs(args) {
+                std::process::exit(1);
+            }
+        }
+        None => {
+            if !convert(&cli.convert) {
+                std::process::exit(1);
+            }
+        }
+    }
 }
@@ -0,0 +1,368 @@
+//! Decoding, editing and encoding of audio resources.
+//!
+//! Audio is decoded to PCM, edited, and encoded to WAV,
+//! since there is no encoder of compressed formats at hand.
+
+use std::{
+    f32::consts::PI,
+    io::{self, Cursor},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// PCM audio with interleaved samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    pub channel_count: usize,
+    pub samples: Vec<f32>,
+}
+
+fn to_io_error(e: SymphoniaError) -> io::Error {
+    match e {
+        SymphoniaError::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+impl Audio {
+    /// Decode audio of any format supported by symphonia.
+    /// `extension` helps to guess the format.
+    pub fn decode(bytes: &[u8], extension: Option<&str>) -> io::Result<Self> {
+        let source =
+            MediaSourceStream::new(Box::new(Cursor::new(bytes.to_vec())), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = extension {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                source,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(to_io_error)?;
+        let mut format = probed.format;
+        let track = format.default_track().ok_or(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No audio track.",
+        ))?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let mut channel_count = track.codec_params.channels.map_or(2, |v| v.count());
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(to_io_error)?;
+
+        let mut samples = Vec::<f32>::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    break
+                }
+                Err(e) => return Err(to_io_error(e)),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            match decoder.decode(&packet) {
+                Ok(buffer) => {
+                    let spec = *buffer.spec();
+                    sample_rate = spec.rate;
+                    channel_count = spec.channels.count();
+                    let mut sample_buffer =
+                        SampleBuffer::<f32>::new(buffer.capacity() as u64, spec);
+                    sample_buffer.copy_interleaved_ref(buffer);
+                    samples.extend_from_slice(sample_buffer.samples());
+                }
+                // Skip corrupted packets.
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(e) => return Err(to_io_error(e)),
+            }
+        }
+
+        Ok(Self {
+            sample_rate,
+            channel_count,
+            samples,
+        })
+    }
+
+    /// Encode to 16-bit WAV.
+    pub fn encode_wav(&self) -> io::Result<Vec<u8>> {
+        let spec = WavSpec {
+            channels: self.channel_count as u16,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut bytes = Cursor::new(Vec::<u8>::new());
+        let mut writer = WavWriter::new(&mut bytes, spec).map_err(io::Error::other)?;
+        for sample in &self.samples {
+            writer
+                .write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+                .map_err(io::Error::other)?;
+        }
+        writer.finalize().map_err(io::Error::other)?;
+        Ok(bytes.into_inner())
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channel_count
+    }
+    pub fn duration_ms(&self) -> f64 {
+        self.frame_count() as f64 * 1000.0 / self.sample_rate as f64
+    }
+    fn ms_to_frames(&self, ms: f64) -> usize {
+        (ms.max(0.0) * self.sample_rate as f64 / 1000.0).round() as usize
+    }
+
+    /// Insert `ms` of silence at the start.
+    pub fn pad_start(&self, ms: f64) -> Self {
+        let mut samples = vec![0.0; self.ms_to_frames(ms) * self.channel_count];
+        samples.extend_from_slice(&self.samples);
+        Self {
+            sample_rate: self.sample_rate,
+            channel_count: self.channel_count,
+            samples,
+        }
+    }
+    /// Remove the first `ms`.
+    pub fn trim_start(&self, ms: f64) -> Self {
+        let begin = self.ms_to_frames(ms).min(self.frame_count()) * self.channel_count;
+        Self {
+            sample_rate: self.sample_rate,
+            channel_count: self.channel_count,
+            samples: self.samples[begin..].to_vec(),
+        }
+    }
+    /// Keep from `start` to `end` in ms.
+    pub fn slice(&self, start: f64, end: f64) -> Self {
+        let frame_count = self.frame_count();
+        let begin = self.ms_to_frames(start).min(frame_count);
+        let end = self.ms_to_frames(end).clamp(begin, frame_count);
+        Self {
+            sample_rate: self.sample_rate,
+            channel_count: self.channel_count,
+            samples: self.samples[begin * self.channel_count..end * self.channel_count].to_vec(),
+        }
+    }
+    /// Fade in at the start and fade out at the end linearly.
+    pub fn fade(&mut self, fade_in: f64, fade_out: f64) {
+        let frame_count = self.frame_count();
+        let fade_in = self.ms_to_frames(fade_in).min(frame_count);
+        let fade_out = self.ms_to_frames(fade_out).min(frame_count);
+        for (idx, frame) in self.samples.chunks_mut(self.channel_count).enumerate() {
+            let mut gain = 1.0f32;
+            if idx < fade_in {
+                gain *= idx as f32 / fade_in as f32;
+            }
+            if frame_count - idx <= fade_out {
+                gain *= (frame_count - idx - 1) as f32 / fade_out as f32;
+            }
+            for sample in frame {
+                *sample *= gain;
+            }
+        }
+    }
+    /// The duration of the start where every sample is quieter than `threshold`.
+    pub fn leading_silence_ms(&self, threshold: f32) -> f64 {
+        let frames = self
+            .samples
+            .iter()
+            .position(|v| v.abs() >= threshold)
+            .unwrap_or(self.samples.len())
+            / self.channel_count;
+        frames as f64 * 1000.0 / self.sample_rate as f64
+    }
+
+    /// Change the speed and the pitch together, like nightcore.
+    pub fn resample(&self, rate: f32) -> Self {
+        let frame_count = (self.frame_count() as f64 / rate as f64) as usize;
+        let mut samples = Vec::with_capacity(frame_count * self.channel_count);
+        for idx in 0..frame_count {
+            let position = idx as f64 * rate as f64;
+            let left = position as usize;
+            let right = (left + 1).min(self.frame_count() - 1);
+            let t = (position - left as f64) as f32;
+            for channel in 0..self.channel_count {
+                let a = self.samples[left * self.channel_count + channel];
+                let b = self.samples[right * self.channel_count + channel];
+                samples.push(a + (b - a) * t);
+            }
+        }
+        Self {
+            sample_rate: self.sample_rate,
+            channel_count: self.channel_count,
+            samples,
+        }
+    }
+
+    /// Change the speed and keep the pitch, using WSOLA.
+    pub fn time_stretch(&self, rate: f32) -> Self {
+        const WINDOW_MS: f64 = 40.0;
+        // Correlation is computed on every `DECIMATION` frames to save time.
+        const DECIMATION: usize = 4;
+
+        let channel_count = self.channel_count;
+        let frame_count = self.frame_count();
+        let window_len = (self.ms_to_frames(WINDOW_MS) / 2 * 2).max(2);
+        let hop_out = window_len / 2;
+        let hop_in = hop_out as f64 * rate as f64;
+        let tolerance = (window_len / 8) as isize;
+        let window = (0..window_len)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / window_len as f32).cos())
+            .collect::<Vec<_>>();
+        let mono = (0..frame_count)
+            .map(|i| {
+                self.samples[i * channel_count..(i + 1) * channel_count]
+                    .iter()
+                    .sum::<f32>()
+            })
+            .collect::<Vec<_>>();
+        let mono_at = |i: usize| mono.get(i).copied().unwrap_or(0.0);
+
+        let out_frame_count = (frame_count as f64 / rate as f64) as usize;
+        let mut samples = vec![0.0f32; (out_frame_count + window_len) * channel_count];
+        let mut previous = 0usize;
+        for k in 0.. {
+            let nominal = (k as f64 * hop_in) as isize;
+            let out_position = k * hop_out;
+            if nominal as usize >= frame_count || out_position >= out_frame_count {
+                break;
+            }
+            // Find the segment most similar to the natural continuation of the previous one.
+            let position = if k == 0 {
+                0
+            } else {
+                let natural = previous + hop_out;
+                let mut best = nominal.max(0) as usize;
+                let mut best_score = f32::NEG_INFINITY;
+                for delta in -tolerance..=tolerance {
+                    let candidate = nominal + delta;
+                    if candidate < 0 {
+                        continue;
+                    }
+                    let candidate = candidate as usize;
+                    let score = (0..hop_out)
+                        .step_by(DECIMATION)
+                        .map(|i| mono_at(natural + i) * mono_at(candidate + i))
+                        .sum::<f32>();
+                    if score > best_score {
+                        best_score = score;
+                        best = candidate;
+                    }
+                }
+                best
+            };
+            for (i, w) in window.iter().enumerate() {
+                if position + i >= frame_count {
+                    break;
+                }
+                for channel in 0..channel_count {
+                    samples[(out_position + i) * channel_count + channel] +=
+                        self.samples[(position + i) * channel_count + channel] * w;
+                }
+            }
+            previous = position;
+        }
+        samples.truncate(out_frame_count * channel_count);
+
+        Self {
+            sample_rate: self.sample_rate,
+            channel_count,
+            samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sine(frequency: f32, ms: usize) -> Audio {
+        let sample_rate = 44100;
+        let frame_count = sample_rate as usize * ms / 1000;
+        let samples = (0..frame_count)
+            .flat_map(|i| {
+                let v = (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin() * 0.5;
+                [v, v]
+            })
+            .collect();
+        Audio {
+            sample_rate,
+            channel_count: 2,
+            samples,
+        }
+    }
+
+    /// Estimate the frequency by counting rising zero crossings.
+    fn frequency(audio: &Audio) -> f32 {
+        let left = audio
+            .samples
+            .iter()
+            .step_by(audio.channel_count)
+            .collect::<Vec<_>>();
+        let crossings = left
+            .windows(2)
+            .filter(|v| *v[0] < 0.0 && *v[1] >= 0.0)
+            .count();
+        crossings as f32 * 1000.0 / audio.duration_ms() as f32
+    }
+
+    #[test]
+    fn audio_wav_round_trip() {
+        let audio = sine(440.0, 100);
+        let decoded = Audio::decode(&audio.encode_wav().unwrap(), Some("wav")).unwrap();
+        assert_eq!(decoded.sample_rate, audio.sample_rate);
+        assert_eq!(decoded.channel_count, audio.channel_count);
+        assert_eq!(decoded.frame_count(), audio.frame_count());
+    }
+
+    #[test]
+    fn audio_pad_and_trim_start() {
+        let audio = sine(440.0, 100);
+        let padded = audio.pad_start(50.0);
+        assert!((padded.duration_ms() - 150.0).abs() < 0.1);
+        assert!((padded.leading_silence_ms(1e-3) - 50.0).abs() < 0.1);
+        assert_eq!(padded.trim_start(50.0), audio);
+    }
+
+    #[test]
+    fn audio_slice_and_fade() {
+        let mut audio = Audio {
+            sample_rate: 1000,
+            channel_count: 2,
+            samples: vec![1.0; 2000],
+        }
+        .slice(200.0, 700.0);
+        assert_eq!(audio.frame_count(), 500);
+        audio.fade(100.0, 100.0);
+        assert_eq!(audio.samples[0], 0.0);
+        assert_eq!(audio.samples[2 * 50], 0.5);
+        assert_eq!(audio.samples[2 * 250], 1.0);
+        assert_eq!(*audio.samples.last().unwrap(), 0.0);
+    }
+
+    #[test]
+    fn audio_resample() {
+        let audio = sine(440.0, 1000);
+        let resampled = audio.resample(1.5);
+        assert!((resampled.duration_ms() - 1000.0 / 1.5).abs() < 1.0);
+        assert!((frequency(&resampled) - 660.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn audio_time_stretch() {
+        let audio = sine(440.0, 1000);
+        let stretched = audio.time_stretch(1.5);
+        assert!((stretched.duration_ms() - 1000.0 / 1.5).abs() < 1.0);
+        assert!((frequency(&stretched) - 440.0).abs() < 10.0);
+    }
+}
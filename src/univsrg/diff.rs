@@ -0,0 +1,498 @@
+//! Comparing two beatmaps by their content rather than their files.
+
+use std::fmt;
+
+use super::types::{Beatmap, BpmTimePoint, EffectTimePoint, LayoutPreset, Object};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectChange {
+    Added(Object),
+    Removed(Object),
+    /// Moved in time, or its end moved, or turned into a note or a long note.
+    Moved {
+        from: Object,
+        to: Object,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum PointChange<T> {
+    Added(T),
+    Removed(T),
+    Changed { from: T, to: T },
+}
+
+#[derive(Debug, Clone)]
+pub struct BeatmapDiff {
+    pub metadata: Vec<FieldChange>,
+    pub bpm_time_points: Vec<PointChange<BpmTimePoint>>,
+    /// Only velocity is compared, since other effects are not in every game.
+    pub effect_time_points: Vec<PointChange<EffectTimePoint>>,
+    pub objects: Vec<ObjectChange>,
+}
+
+impl BeatmapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+            && self.bpm_time_points.is_empty()
+            && self.effect_time_points.is_empty()
+            && self.objects.is_empty()
+    }
+}
+
+pub struct DiffOptions {
+    /// Times differing by up to this in ms are the same, e.g. after rounding by a format.
+    pub tolerance: i32,
+    /// Objects shifted by up to this in ms are moved rather than removed and added.
+    pub max_move: i32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            tolerance: 1,
+            max_move: 100,
+        }
+    }
+}
+
+fn metadata(beatmap: &Beatmap) -> Vec<(&'static str, Option<String>)> {
+    let path = |v: Option<&super::resource::ResourceEntry>| {
+        v.map(|v| v.original_path.to_string_lossy().into_owned())
+    };
+    let joined = |v: Vec<String>| Some(v.join(", ")).filter(|v| !v.is_empty());
+    let mut online_ids = beatmap.online_ids.iter().collect::<Vec<_>>();
+    online_ids.sort_by_key(|v| v.0);
+    let id = |v: Option<i64>| v.map_or("?".to_owned(), |v| v.to_string());
+    let storyboard = &beatmap.storyboard;
+    vec![
+        ("title", beatmap.title.latin.clone()),
+        ("title_unicode", beatmap.title.unicode.clone()),
+        ("artist", beatmap.artist.latin.clone()),
+        ("artist_unicode", beatmap.artist.unicode.clone()),
+        ("version", beatmap.version.clone()),
+        ("creator", beatmap.creator.clone()),
+        ("source", beatmap.source.clone()),
+        (
+            "tags",
+            Some(beatmap.tags.join(" ")).filter(|v| !v.is_empty()),
+        ),
+        (
+            "online_ids",
+            joined(
+                online_ids
+                    .iter()
+                    .map(|(game, ids)| {
+                        format!(
+                            "{} {}/{}",
+                            game.name(),
+                            id(ids.beatmap_id),
+                            id(ids.beatmap_set_id)
+                        )
+                    })
+                    .collect(),
+            ),
+        ),
+        ("genre", beatmap.genre.clone()),
+        ("language", beatmap.language.clone()),
+        (
+            "custom_fields",
+            joined(
+                beatmap
+                    .custom_fields
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect(),
+            ),
+        ),
+        ("column_count", beatmap.column_count.map(|v| v.to_string())),
+        (
+            "layout",
+            beatmap.layout.as_ref().map(|v| match v.preset {
+                LayoutPreset::Custom => format!("{} {:?}", v.preset.name(), v.columns),
+                _ => v.preset.name().to_owned(),
+            }),
+        ),
+        ("audio", path(beatmap.audio.as_ref())),
+        (
+            "audio_lead_in",
+            beatmap.audio_lead_in.map(|v| v.to_string()),
+        ),
+        ("background", path(beatmap.background.as_ref())),
+        (
+            "video",
+            beatmap.video.as_ref().map(|v| {
+                format!(
+                    "{} at {} ms",
+                    v.resource.original_path.to_string_lossy(),
+                    v.offset
+                )
+            }),
+        ),
+        (
+            "storyboard",
+            (!storyboard.is_empty()).then(|| {
+                format!(
+                    "{} with {} events and {} resources",
+                    path(storyboard.script.as_ref()).unwrap_or("no script".to_owned()),
+                    storyboard.events.len(),
+                    storyboard.resources.len()
+                )
+            }),
+        ),
+        ("preview_time", beatmap.preview_time.map(|v| v.to_string())),
+        (
+            "preview_duration",
+            beatmap.preview_duration.map(|v| v.to_string()),
+        ),
+        (
+            "hp_difficulty",
+            beatmap.hp_difficulty.map(|v| v.to_string()),
+        ),
+        (
+            "acc_difficulty",
+            beatmap.acc_difficulty.map(|v| v.to_string()),
+        ),
+        (
+            "hit_windows",
+            beatmap.hit_windows.map(|v| {
+                format!(
+                    "{}/{}/{}/{}/{}/{} ms",
+                    v.perfect, v.great, v.good, v.ok, v.meh, v.miss
+                )
+            }),
+        ),
+        (
+            "breaks",
+            joined(
+                beatmap
+                    .breaks
+                    .iter()
+                    .map(|v| format!("{}-{} ms", v.offset, v.end_offset))
+                    .collect(),
+            ),
+        ),
+    ]
+}
+
+/// Pair points of `old` and `new` at about the same offsets.
+fn diff_points<T: Clone>(
+    old: &[T],
+    new: &[T],
+    offset: impl Fn(&T) -> i32,
+    is_same: impl Fn(&T, &T) -> bool,
+    tolerance: i32,
+) -> Vec<PointChange<T>> {
+    let mut changes = vec![];
+    let mut matched = vec![false; new.len()];
+    for o in old {
+        let found = new
+            .iter()
+            .enumerate()
+            .find(|(i, n)| !matched[*i] && (offset(n) - offset(o)).abs() <= tolerance);
+        match found {
+            Some((i, n)) => {
+                matched[i] = true;
+                if !is_same(o, n) {
+                    changes.push(PointChange::Changed {
+                        from: o.clone(),
+                        to: n.clone(),
+                    });
+                }
+            }
+            None => changes.push(PointChange::Removed(o.clone())),
+        }
+    }
+    for (n, matched) in new.iter().zip(matched) {
+        if !matched {
+            changes.push(PointChange::Added(n.clone()));
+        }
+    }
+    changes.sort_by_key(|v| match v {
+        PointChange::Added(v) | PointChange::Removed(v) | PointChange::Changed { from: v, .. } => {
+            offset(v)
+        }
+    });
+    changes
+}
+
+/// Effect time points that change velocity, since formats differ in redundant points.
+fn velocity_changes(beatmap: &Beatmap) -> Vec<EffectTimePoint> {
+    let mut velocity = 1.0;
+    let mut changes = vec![];
+    for etp in &beatmap.effect_time_points {
+        if (etp.velocity_multiplier - velocity).abs() > 1e-3 {
+            changes.push(etp.clone());
+        }
+        velocity = etp.velocity_multiplier;
+    }
+    changes
+}
+
+fn diff_objects(old: &Beatmap, new: &Beatmap, options: &DiffOptions) -> Vec<ObjectChange> {
+    let is_close = |a: i32, b: i32, limit: i32| (a - b).abs() <= limit;
+    let is_same = |a: &Object, b: &Object| {
+        a.column() == b.column()
+            && is_close(a.offset(), b.offset(), options.tolerance)
+            && match (a.end_offset(), b.end_offset()) {
+                (Some(a), Some(b)) => is_close(a, b, options.tolerance),
+                (None, None) => true,
+                _ => false,
+            }
+    };
+
+    let mut old_left = old.objects.clone();
+    let mut new_left = new.objects.clone();
+    old_left.sort_by_key(|v| (v.column(), v.offset()));
+    new_left.sort_by_key(|v| (v.column(), v.offset()));
+    // Match the same objects first, then the moved ones by distance.
+    old_left.retain(|o| match new_left.iter().position(|n| is_same(o, n)) {
+        Some(i) => {
+            new_left.remove(i);
+            false
+        }
+        None => true,
+    });
+    let mut changes = vec![];
+    old_left.retain(|o| {
+        let nearest = new_left
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| {
+                n.column() == o.column() && is_close(n.offset(), o.offset(), options.max_move)
+            })
+            .min_by_key(|(_, n)| (n.offset() - o.offset()).abs());
+        match nearest {
+            Some((i, _)) => {
+                changes.push(ObjectChange::Moved {
+                    from: *o,
+                    to: new_left.remove(i),
+                });
+                false
+            }
+            None => true,
+        }
+    });
+    changes.extend(old_left.into_iter().map(ObjectChange::Removed));
+    changes.extend(new_left.into_iter().map(ObjectChange::Added));
+    changes.sort_by_key(|v| match v {
+        ObjectChange::Added(v) | ObjectChange::Removed(v) | ObjectChange::Moved { from: v, .. } => {
+            (v.offset(), v.column())
+        }
+    });
+    changes
+}
+
+/// Compare `old` with `new`.
+pub fn diff(old: &Beatmap, new: &Beatmap, options: &DiffOptions) -> BeatmapDiff {
+    let metadata = metadata(old)
+        .into_iter()
+        .zip(metadata(new))
+        .filter(|(o, n)| o.1 != n.1)
+        .map(|(o, n)| FieldChange {
+            field: o.0,
+            from: o.1,
+            to: n.1,
+        })
+        .collect();
+    let bpm_time_points = diff_points(
+        &old.bpm_time_points,
+        &new.bpm_time_points,
+        |v| v.offset,
+        |a, b| (a.bpm - b.bpm).abs() <= 1e-3 && a.time_signature == b.time_signature,
+        options.tolerance,
+    );
+    let effect_time_points = diff_points(
+        &velocity_changes(old),
+        &velocity_changes(new),
+        |v| v.offset,
+        |a, b| (a.velocity_multiplier - b.velocity_multiplier).abs() <= 1e-3,
+        options.tolerance,
+    );
+    BeatmapDiff {
+        metadata,
+        bpm_time_points,
+        effect_time_points,
+        objects: diff_objects(old, new, options),
+    }
+}
+
+fn format_object(object: &Object) -> String {
+    match object {
+        Object::Note { column, offset } => format!("note at {} ms in column {}", offset, column),
+        Object::LongNote {
+            column,
+            offset,
+            end_offset,
+        } => format!(
+            "long note at {}-{} ms in column {}",
+            offset, end_offset, column
+        ),
+    }
+}
+
+impl fmt::Display for BeatmapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = |v: &Option<String>| v.clone().unwrap_or("(none)".to_owned());
+        for change in &self.metadata {
+            writeln!(
+                f,
+                "~ {}: {} -> {}",
+                change.field,
+                text(&change.from),
+                text(&change.to)
+            )?;
+        }
+        let bpm = |v: &BpmTimePoint| {
+            format!(
+                "BPM {} {}/{} at {} ms",
+                v.bpm, v.time_signature.numerator, v.time_signature.denominator, v.offset
+            )
+        };
+        for change in &self.bpm_time_points {
+            match change {
+                PointChange::Added(v) => writeln!(f, "+ {}", bpm(v))?,
+                PointChange::Removed(v) => writeln!(f, "- {}", bpm(v))?,
+                PointChange::Changed { from, to } => writeln!(f, "~ {} -> {}", bpm(from), bpm(to))?,
+            }
+        }
+        let sv = |v: &EffectTimePoint| format!("SV {}x at {} ms", v.velocity_multiplier, v.offset);
+        for change in &self.effect_time_points {
+            match change {
+                PointChange::Added(v) => writeln!(f, "+ {}", sv(v))?,
+                PointChange::Removed(v) => writeln!(f, "- {}", sv(v))?,
+                PointChange::Changed { from, to } => writeln!(f, "~ {} -> {}", sv(from), sv(to))?,
+            }
+        }
+        for change in &self.objects {
+            match change {
+                ObjectChange::Added(v) => writeln!(f, "+ {}", format_object(v))?,
+                ObjectChange::Removed(v) => writeln!(f, "- {}", format_object(v))?,
+                ObjectChange::Moved { from, to } => {
+                    writeln!(f, "~ {} -> {}", format_object(from), format_object(to))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::{
+        judgement::HitWindows,
+        types::{BreakPeriod, TimeSignature},
+    };
+
+    #[test]
+    fn diff_beatmaps() {
+        let mut old = Beatmap::new();
+        old.version = Some("Hard".to_owned());
+        old.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        old.effect_time_points = vec![
+            EffectTimePoint::new(0, 1.0),
+            EffectTimePoint::new(1000, 0.5),
+        ];
+        old.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 500,
+            },
+            Object::Note {
+                column: 1,
+                offset: 1000,
+            },
+            Object::LongNote {
+                column: 2,
+                offset: 1500,
+                end_offset: 2000,
+            },
+            Object::Note {
+                column: 3,
+                offset: 2500,
+            },
+        ];
+
+        let mut new = old.clone();
+        new.version = Some("Insane".to_owned());
+        // The redundant point at 0 is gone, and the SV changes.
+        new.effect_time_points = vec![EffectTimePoint::new(1001, 0.75)];
+        new.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 501,
+            },
+            Object::Note {
+                column: 1,
+                offset: 1050,
+            },
+            Object::LongNote {
+                column: 2,
+                offset: 1500,
+                end_offset: 2250,
+            },
+            Object::Note {
+                column: 0,
+                offset: 3000,
+            },
+        ];
+
+        let diff = diff(&old, &new, &DiffOptions::default());
+        assert_eq!(diff.metadata.len(), 1);
+        assert_eq!(diff.metadata[0].field, "version");
+        assert!(diff.bpm_time_points.is_empty());
+        assert!(matches!(
+            diff.effect_time_points[..],
+            [PointChange::Changed { .. }]
+        ));
+        assert_eq!(
+            diff.objects,
+            vec![
+                ObjectChange::Moved {
+                    from: old.objects[1],
+                    to: new.objects[1],
+                },
+                ObjectChange::Moved {
+                    from: old.objects[2],
+                    to: new.objects[2],
+                },
+                ObjectChange::Removed(old.objects[3]),
+                ObjectChange::Added(new.objects[3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_metadata() {
+        let old = Beatmap::new();
+        let mut new = old.clone();
+        new.genre = Some("Electronic".to_owned());
+        new.custom_fields
+            .insert("WidescreenStoryboard".to_owned(), "1".to_owned());
+        new.hit_windows = Some(HitWindows::from_osu_od(8.0));
+        new.breaks.push(BreakPeriod {
+            offset: 1000,
+            end_offset: 3000,
+        });
+
+        let diff = diff(&old, &new, &DiffOptions::default());
+        let fields = diff.metadata.iter().map(|v| v.field).collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            vec!["genre", "custom_fields", "hit_windows", "breaks"]
+        );
+        assert_eq!(diff.metadata[3].to.as_deref(), Some("1000-3000 ms"));
+    }
+}
@@ -0,0 +1,390 @@
+//! Skillset ratings in the style of MinaCalc, the difficulty calculator of Etterna, for 4K.
+//!
+//! Like MinaCalc, the chart is cut into half-second intervals, each interval gets a difficulty
+//! per skillset from the speed of each hand and modifiers for its patterns,
+//! and the rating is the lowest skill that would score the goal over all intervals.
+//! The modifiers are simplified, so ratings are close to but not the same as Etterna.
+
+use std::io;
+
+use super::super::types::Beatmap;
+
+const INTERVAL_SPAN: f64 = 0.5;
+/// Wife score to reach, 93% like MinaCalc.
+const SCORE_GOAL: f64 = 0.93;
+/// Scale ratings to about the range of MSD.
+const RATING_SCALE: f64 = 2.4;
+/// Faster than this in ms is a flam rather than two hits.
+const MIN_MS: f64 = 25.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skillset {
+    Stream,
+    Jumpstream,
+    Handstream,
+    Stamina,
+    JackSpeed,
+    Chordjack,
+    Technical,
+}
+
+impl Skillset {
+    pub const ALL: [Skillset; 7] = [
+        Skillset::Stream,
+        Skillset::Jumpstream,
+        Skillset::Handstream,
+        Skillset::Stamina,
+        Skillset::JackSpeed,
+        Skillset::Chordjack,
+        Skillset::Technical,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Skillset::Stream => "Stream",
+            Skillset::Jumpstream => "Jumpstream",
+            Skillset::Handstream => "Handstream",
+            Skillset::Stamina => "Stamina",
+            Skillset::JackSpeed => "JackSpeed",
+            Skillset::Chordjack => "Chordjack",
+            Skillset::Technical => "Technical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkillsetRatings {
+    pub rate: f32,
+    /// The highest rating of the skillsets.
+    pub overall: f32,
+    /// In the order of [Skillset::ALL].
+    pub ratings: [f32; 7],
+}
+
+impl SkillsetRatings {
+    pub fn get(&self, skillset: Skillset) -> f32 {
+        self.ratings[Skillset::ALL.iter().position(|v| *v == skillset).unwrap()]
+    }
+    /// The skillset with the highest rating.
+    pub fn main_skillset(&self) -> Skillset {
+        Skillset::ALL
+            .into_iter()
+            .max_by(|a, b| self.get(*a).total_cmp(&self.get(*b)))
+            .unwrap()
+    }
+}
+
+/// What happens in an interval.
+#[derive(Debug, Clone, Default)]
+struct Interval {
+    /// Taps of the left hand and the right hand.
+    taps: [u32; 2],
+    /// Sum of the speed in hits per second from the previous hit of the same hand.
+    hand_speed: [f64; 2],
+    /// Rows by the number of notes.
+    rows: [u32; 5],
+    /// Taps in the same column as the previous row.
+    jack_taps: u32,
+    /// The fastest jack in hits per second.
+    jack_speed: f64,
+    /// Chords sharing columns with the previous row, and the sum of their speed.
+    chord_jacks: u32,
+    chord_jack_speed: f64,
+    /// Time between rows in ms.
+    gaps: Vec<f64>,
+}
+
+impl Interval {
+    fn row_count(&self) -> u32 {
+        self.rows.iter().sum()
+    }
+    fn tap_count(&self) -> u32 {
+        self.taps.iter().sum()
+    }
+    /// The ratio of rows with `size` notes.
+    fn row_ratio(&self, size: usize) -> f64 {
+        match self.row_count() {
+            0 => 0.0,
+            count => self.rows[size] as f64 / count as f64,
+        }
+    }
+
+    /// Speed of the harder hand, mixing density and the time between hits.
+    fn hand_base(&self) -> f64 {
+        (0..2)
+            .map(|hand| {
+                let taps = self.taps[hand];
+                if taps == 0 {
+                    return 0.0;
+                }
+                let nps = taps as f64 / INTERVAL_SPAN;
+                let speed = self.hand_speed[hand] / taps as f64;
+                (nps + speed) / 2.0
+            })
+            .fold(0.0, f64::max)
+    }
+
+    fn jack_ratio(&self) -> f64 {
+        match self.tap_count() {
+            0 => 0.0,
+            count => self.jack_taps as f64 / count as f64,
+        }
+    }
+
+    fn stream(&self) -> f64 {
+        let penalty = 0.8 * (self.row_ratio(2) - 0.25).max(0.0)
+            + 1.2 * self.row_ratio(3)
+            + 1.5 * self.row_ratio(4);
+        let pattern = (1.0 - penalty).clamp(0.4, 1.0);
+        self.hand_base() * pattern * (1.0 - 0.5 * self.jack_ratio())
+    }
+
+    fn jumpstream(&self) -> f64 {
+        let jumps = (0.55 + 0.9 * self.row_ratio(2).min(0.5)).min(1.0);
+        let penalty = 1.0 * self.row_ratio(3) + 1.5 * self.row_ratio(4);
+        let pattern = jumps * (1.0 - penalty).clamp(0.4, 1.0);
+        self.hand_base() * pattern * (1.0 - 0.5 * self.jack_ratio())
+    }
+
+    fn handstream(&self) -> f64 {
+        let hands = (0.5 + 1.5 * self.row_ratio(3).min(0.33)).min(1.0);
+        let pattern = hands * (1.0 - 1.2 * self.row_ratio(4)).clamp(0.4, 1.0);
+        self.hand_base() * pattern * (1.0 - 0.5 * self.jack_ratio())
+    }
+
+    fn jack_speed(&self) -> f64 {
+        self.jack_speed * 0.9
+    }
+
+    fn chordjack(&self) -> f64 {
+        if self.chord_jacks == 0 {
+            return 0.0;
+        }
+        let chord_ratio = 1.0 - self.row_ratio(1);
+        let speed = self.chord_jack_speed / self.chord_jacks as f64;
+        // Wider chords are harder to jack.
+        let width = self.tap_count() as f64 / self.row_count() as f64;
+        speed * (0.5 + 0.5 * chord_ratio) * (width / 2.0).sqrt()
+    }
+
+    /// Density adjusted by how irregular the rhythm is.
+    fn technical(&self) -> f64 {
+        let n = self.gaps.len();
+        if n < 2 {
+            return self.hand_base() * 0.6;
+        }
+        let mean = self.gaps.iter().sum::<f64>() / n as f64;
+        let variance = self.gaps.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n as f64;
+        let variation = variance.sqrt() / mean;
+        self.hand_base() * (0.6 + 0.8 * variation).clamp(0.6, 1.05)
+    }
+}
+
+fn intervals(beatmap: &Beatmap, rate: f64) -> Vec<Interval> {
+    // Long notes count as taps at their heads, like MinaCalc.
+    let mut rows = Vec::<(f64, u8)>::new();
+    let mut objects = beatmap.objects.clone();
+    objects.sort_by_key(|v| v.offset());
+    for object in objects {
+        let time = object.offset() as f64 / rate / 1000.0;
+        let bit = 1 << object.column().min(3);
+        match rows.last_mut() {
+            Some(row) if row.0 == time => row.1 |= bit,
+            _ => rows.push((time, bit)),
+        }
+    }
+    let Some(last) = rows.last() else {
+        return vec![];
+    };
+
+    let mut intervals = vec![Interval::default(); (last.0 / INTERVAL_SPAN) as usize + 1];
+    let mut column_times = [None::<f64>; 4];
+    let mut hand_times = [None::<f64>; 2];
+    let mut previous = None::<(f64, u8)>;
+    for (time, mask) in rows {
+        let interval = &mut intervals[(time.max(0.0) / INTERVAL_SPAN) as usize];
+        let size = mask.count_ones() as usize;
+        interval.rows[size] += 1;
+        if let Some((previous_time, previous_mask)) = previous {
+            let ms = (time - previous_time) * 1000.0;
+            interval.gaps.push(ms);
+            if size >= 2 && mask & previous_mask != 0 {
+                interval.chord_jacks += 1;
+                interval.chord_jack_speed += 1000.0 / ms.max(MIN_MS);
+            }
+        }
+        for (hand, hand_time) in hand_times.iter_mut().enumerate() {
+            let taps = (mask >> (2 * hand) & 0b11).count_ones();
+            if taps == 0 {
+                continue;
+            }
+            interval.taps[hand] += taps;
+            if let Some(hand_time) = *hand_time {
+                let ms = (time - hand_time) * 1000.0;
+                interval.hand_speed[hand] += taps as f64 * 1000.0 / ms.max(MIN_MS);
+            }
+            *hand_time = Some(time);
+        }
+        for (column, column_time) in column_times.iter_mut().enumerate() {
+            if mask & 1 << column == 0 {
+                continue;
+            }
+            if let Some(column_time) = *column_time {
+                let ms = (time - column_time) * 1000.0;
+                interval.jack_speed = interval.jack_speed.max(1000.0 / ms.max(MIN_MS));
+                if previous.is_some_and(|v| v.1 & 1 << column != 0) {
+                    interval.jack_taps += 1;
+                }
+            }
+            *column_time = Some(time);
+        }
+        previous = Some((time, mask));
+    }
+    intervals
+}
+
+/// The lowest skill scoring [SCORE_GOAL] on intervals of `difficulties` with `weights` taps.
+/// An interval harder than the skill loses points by how much harder it is.
+fn chisel(difficulties: &[f64], weights: &[f64]) -> f64 {
+    let total = weights.iter().sum::<f64>();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let score = |skill: f64| {
+        difficulties
+            .iter()
+            .zip(weights)
+            .map(|(difficulty, weight)| match skill >= *difficulty {
+                true => *weight,
+                false => weight * (skill / difficulty).powf(1.7),
+            })
+            .sum::<f64>()
+            / total
+    };
+    let (mut low, mut high) = (0.0, difficulties.iter().copied().fold(0.0, f64::max));
+    for _ in 0..50 {
+        let middle = (low + high) / 2.0;
+        match score(middle) >= SCORE_GOAL {
+            true => high = middle,
+            false => low = middle,
+        }
+    }
+    high
+}
+
+/// Difficulties adjusted for keeping up the pace, from the hardest skillset of every interval.
+fn stamina(difficulties: &[f64]) -> Vec<f64> {
+    let peak = difficulties.iter().copied().fold(0.0, f64::max);
+    if peak == 0.0 {
+        return difficulties.to_vec();
+    }
+    // Fatigue builds up and recovers over about 10 s.
+    let mut fatigue = 0.0;
+    difficulties
+        .iter()
+        .map(|difficulty| {
+            fatigue = fatigue * 0.95 + 0.05 * difficulty / peak;
+            difficulty * (0.78 + 0.2 * fatigue)
+        })
+        .collect()
+}
+
+/// Skillset ratings of a 4K beatmap played at `rate`.
+pub fn skillset_ratings(beatmap: &Beatmap, rate: f32) -> io::Result<SkillsetRatings> {
+    if beatmap.column_count != Some(4) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Skillset ratings are only for 4K.",
+        ));
+    }
+    if !(rate > 0.0 && rate.is_finite()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Rate should be positive.",
+        ));
+    }
+    let intervals = intervals(beatmap, rate as f64);
+    let weights = intervals
+        .iter()
+        .map(|v| v.tap_count() as f64)
+        .collect::<Vec<_>>();
+    let difficulties = |f: fn(&Interval) -> f64| intervals.iter().map(f).collect::<Vec<_>>();
+
+    let stream = difficulties(Interval::stream);
+    let jumpstream = difficulties(Interval::jumpstream);
+    let handstream = difficulties(Interval::handstream);
+    let technical = difficulties(Interval::technical);
+    let hardest = (0..intervals.len())
+        .map(|i| {
+            stream[i]
+                .max(jumpstream[i])
+                .max(handstream[i])
+                .max(technical[i])
+        })
+        .collect::<Vec<_>>();
+    let by_skillset = [
+        stream.clone(),
+        jumpstream.clone(),
+        handstream.clone(),
+        stamina(&hardest),
+        difficulties(Interval::jack_speed),
+        difficulties(Interval::chordjack),
+        technical.clone(),
+    ];
+
+    let mut ratings = [0.0f32; 7];
+    for (rating, difficulties) in ratings.iter_mut().zip(&by_skillset) {
+        *rating = (chisel(difficulties, &weights) * RATING_SCALE) as f32;
+    }
+    Ok(SkillsetRatings {
+        rate,
+        overall: ratings.iter().copied().fold(0.0, f32::max),
+        ratings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::Object;
+
+    fn beatmap(rows: impl IntoIterator<Item = (i32, Vec<u32>)>) -> Beatmap {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        for (offset, columns) in rows {
+            for column in columns {
+                beatmap.objects.push(Object::Note { column, offset });
+            }
+        }
+        beatmap
+    }
+
+    #[test]
+    fn skillset_ratings_patterns() {
+        // 1/4 stream at 180 BPM for 30 s.
+        let stream = beatmap((0..360).map(|i| (i * 83, vec![[0, 2, 1, 3][i as usize % 4]])));
+        let ratings = skillset_ratings(&stream, 1.0).unwrap();
+        assert_eq!(ratings.main_skillset(), Skillset::Stream);
+
+        // 1/4 jacks of chords at 150 BPM for 30 s.
+        let chordjack = beatmap((0..300).map(|i| {
+            (
+                i * 100,
+                [vec![0, 1, 2], vec![1, 2, 3]][i as usize / 2 % 2].clone(),
+            )
+        }));
+        let ratings = skillset_ratings(&chordjack, 1.0).unwrap();
+        assert_eq!(ratings.main_skillset(), Skillset::Chordjack);
+
+        let faster = skillset_ratings(&stream, 1.2).unwrap();
+        let stream_rating = skillset_ratings(&stream, 1.0).unwrap().overall;
+        assert!(faster.overall > stream_rating * 1.1);
+
+        let mut seven_keys = stream.clone();
+        seven_keys.column_count = Some(7);
+        assert!(skillset_ratings(&seven_keys, 1.0).is_err());
+        assert!(skillset_ratings(&stream, 0.0).is_err());
+        assert!(skillset_ratings(&stream, f32::NAN).is_err());
+        assert!(skillset_ratings(&stream, f32::INFINITY).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+//! Difficulty ratings of beatmaps as calculated by games.
+
+pub mod etterna;
+pub mod osu_mania;
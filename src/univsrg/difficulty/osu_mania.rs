@@ -0,0 +1,492 @@
+//! Star rating of osu!mania, following the strain skill of the game.
+//! https://github.com/ppy/osu/tree/master/osu.Game.Rulesets.Mania/Difficulty
+
+use super::super::types::{Beatmap, Object};
+
+const STAR_SCALING_FACTOR: f64 = 0.018;
+const SECTION_LENGTH: f64 = 400.0;
+const DECAY_WEIGHT: f64 = 0.9;
+const INDIVIDUAL_DECAY_BASE: f64 = 0.125;
+const OVERALL_DECAY_BASE: f64 = 0.30;
+const RELEASE_THRESHOLD: f64 = 30.0;
+
+/// Mods changing the star rating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ManiaMods {
+    /// 1.5 for Double Time, 0.75 for Half Time.
+    pub rate: f64,
+    /// Hold Off, which turns long notes into notes.
+    pub hold_off: bool,
+}
+
+impl Default for ManiaMods {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            hold_off: false,
+        }
+    }
+}
+
+fn apply_decay(value: f64, delta_time: f64, decay_base: f64) -> f64 {
+    value * decay_base.powf(delta_time / 1000.0)
+}
+
+/// `a` is larger than `b` by more than 1 ms.
+fn definitely_bigger(a: f64, b: f64) -> bool {
+    a - b > 1.0
+}
+
+/// The unstable sort of .NET Framework, which osu!lazer keeps in `LegacySortHelper`
+/// to order objects at the same time like osu!stable.
+mod legacy_sort {
+    use std::cmp::Ordering;
+
+    const INTROSORT_SIZE_THRESHOLD: usize = 16;
+
+    pub fn sort<T: Copy>(keys: &mut [T], compare: impl Fn(&T, &T) -> Ordering) {
+        if keys.len() < 2 {
+            return;
+        }
+        let depth_limit = 2 * floor_log2(keys.len());
+        intro_sort(keys, 0, keys.len() - 1, depth_limit, &compare);
+    }
+
+    /// One more than the floor of log2, as .NET has it.
+    fn floor_log2(mut n: usize) -> usize {
+        let mut result = 0;
+        while n >= 1 {
+            result += 1;
+            n /= 2;
+        }
+        result
+    }
+
+    fn is_less<T>(compare: &impl Fn(&T, &T) -> Ordering, a: &T, b: &T) -> bool {
+        compare(a, b) == Ordering::Less
+    }
+
+    fn swap_if_greater<T>(
+        keys: &mut [T],
+        compare: &impl Fn(&T, &T) -> Ordering,
+        a: usize,
+        b: usize,
+    ) {
+        if a != b && compare(&keys[a], &keys[b]) == Ordering::Greater {
+            keys.swap(a, b);
+        }
+    }
+
+    fn intro_sort<T: Copy>(
+        keys: &mut [T],
+        lo: usize,
+        mut hi: usize,
+        mut depth_limit: usize,
+        compare: &impl Fn(&T, &T) -> Ordering,
+    ) {
+        while hi > lo {
+            let partition_size = hi - lo + 1;
+            if partition_size <= INTROSORT_SIZE_THRESHOLD {
+                match partition_size {
+                    2 => swap_if_greater(keys, compare, lo, hi),
+                    3 => {
+                        swap_if_greater(keys, compare, lo, hi - 1);
+                        swap_if_greater(keys, compare, lo, hi);
+                        swap_if_greater(keys, compare, hi - 1, hi);
+                    }
+                    _ => insertion_sort(keys, lo, hi, compare),
+                }
+                return;
+            }
+            if depth_limit == 0 {
+                heap_sort(keys, lo, hi, compare);
+                return;
+            }
+            depth_limit -= 1;
+            let p = pick_pivot_and_partition(keys, lo, hi, compare);
+            intro_sort(keys, p + 1, hi, depth_limit, compare);
+            hi = p - 1;
+        }
+    }
+
+    fn pick_pivot_and_partition<T: Copy>(
+        keys: &mut [T],
+        lo: usize,
+        hi: usize,
+        compare: &impl Fn(&T, &T) -> Ordering,
+    ) -> usize {
+        let middle = lo + ((hi - lo) >> 1);
+        swap_if_greater(keys, compare, lo, middle);
+        swap_if_greater(keys, compare, lo, hi);
+        swap_if_greater(keys, compare, middle, hi);
+        let pivot = keys[middle];
+        keys.swap(middle, hi - 1);
+        let (mut left, mut right) = (lo, hi - 1);
+        while left < right {
+            left += 1;
+            while is_less(compare, &keys[left], &pivot) {
+                left += 1;
+            }
+            right -= 1;
+            while is_less(compare, &pivot, &keys[right]) {
+                right -= 1;
+            }
+            if left >= right {
+                break;
+            }
+            keys.swap(left, right);
+        }
+        keys.swap(left, hi - 1);
+        left
+    }
+
+    fn heap_sort<T: Copy>(
+        keys: &mut [T],
+        lo: usize,
+        hi: usize,
+        compare: &impl Fn(&T, &T) -> Ordering,
+    ) {
+        let n = hi - lo + 1;
+        for i in (1..=n / 2).rev() {
+            down_heap(keys, i, n, lo, compare);
+        }
+        for i in (2..=n).rev() {
+            keys.swap(lo, lo + i - 1);
+            down_heap(keys, 1, i - 1, lo, compare);
+        }
+    }
+
+    fn down_heap<T: Copy>(
+        keys: &mut [T],
+        mut i: usize,
+        n: usize,
+        lo: usize,
+        compare: &impl Fn(&T, &T) -> Ordering,
+    ) {
+        let d = keys[lo + i - 1];
+        while i <= n / 2 {
+            let mut child = 2 * i;
+            if child < n && is_less(compare, &keys[lo + child - 1], &keys[lo + child]) {
+                child += 1;
+            }
+            if !is_less(compare, &d, &keys[lo + child - 1]) {
+                break;
+            }
+            keys[lo + i - 1] = keys[lo + child - 1];
+            i = child;
+        }
+        keys[lo + i - 1] = d;
+    }
+
+    fn insertion_sort<T: Copy>(
+        keys: &mut [T],
+        lo: usize,
+        hi: usize,
+        compare: &impl Fn(&T, &T) -> Ordering,
+    ) {
+        for i in lo..hi {
+            let t = keys[i + 1];
+            let mut j = i + 1;
+            while j > lo && is_less(compare, &t, &keys[j - 1]) {
+                keys[j] = keys[j - 1];
+                j -= 1;
+            }
+            keys[j] = t;
+        }
+    }
+}
+
+struct Strain {
+    start_times: Vec<f64>,
+    end_times: Vec<f64>,
+    individual_strains: Vec<f64>,
+    individual_strain: f64,
+    overall_strain: f64,
+}
+
+impl Strain {
+    fn new(column_count: usize) -> Self {
+        Self {
+            start_times: vec![0.0; column_count],
+            end_times: vec![0.0; column_count],
+            individual_strains: vec![0.0; column_count],
+            individual_strain: 0.0,
+            overall_strain: 1.0,
+        }
+    }
+
+    fn strain_value_of(&mut self, column: usize, start: f64, end: f64, delta_time: f64) -> f64 {
+        // The lowest value we can assume with the current information.
+        let mut closest_end_time = (end - start).abs();
+        // Holding something else makes everything harder.
+        let mut hold_factor = 1.0;
+        let mut is_overlapping = false;
+        for i in 0..self.end_times.len() {
+            is_overlapping |= definitely_bigger(self.end_times[i], start)
+                && definitely_bigger(end, self.end_times[i])
+                && definitely_bigger(start, self.start_times[i]);
+            if definitely_bigger(self.end_times[i], end)
+                && definitely_bigger(start, self.start_times[i])
+            {
+                hold_factor = 1.25;
+            }
+            closest_end_time = closest_end_time.min((end - self.end_times[i]).abs());
+        }
+        // Releasing awkwardly is harder, unless another note ends at about the same time.
+        let hold_addition = match is_overlapping {
+            true => 1.0 / (1.0 + (0.27 * (RELEASE_THRESHOLD - closest_end_time)).exp()),
+            false => 0.0,
+        };
+
+        self.individual_strains[column] = apply_decay(
+            self.individual_strains[column],
+            start - self.start_times[column],
+            INDIVIDUAL_DECAY_BASE,
+        );
+        self.individual_strains[column] += 2.0 * hold_factor;
+        // A chord takes the hardest column.
+        self.individual_strain = match delta_time <= 1.0 {
+            true => self.individual_strain.max(self.individual_strains[column]),
+            false => self.individual_strains[column],
+        };
+
+        self.overall_strain = apply_decay(self.overall_strain, delta_time, OVERALL_DECAY_BASE);
+        self.overall_strain += (1.0 + hold_addition) * hold_factor;
+
+        self.start_times[column] = start;
+        self.end_times[column] = end;
+        self.individual_strain + self.overall_strain
+    }
+
+    /// The strain at `time` with nothing hit since `previous_start`.
+    fn initial_strain(&self, time: f64, previous_start: f64) -> f64 {
+        apply_decay(
+            self.individual_strain,
+            time - previous_start,
+            INDIVIDUAL_DECAY_BASE,
+        ) + apply_decay(
+            self.overall_strain,
+            time - previous_start,
+            OVERALL_DECAY_BASE,
+        )
+    }
+}
+
+/// The strain peak of every 400 ms section.
+fn strain_peaks(beatmap: &Beatmap, mods: &ManiaMods) -> Vec<f64> {
+    let column_count = beatmap
+        .objects
+        .iter()
+        .map(|v| v.column() + 1)
+        .chain(beatmap.column_count)
+        .max()
+        .unwrap_or(0) as usize;
+    let mut objects = beatmap
+        .objects
+        .iter()
+        .map(|object| match *object {
+            Object::LongNote { column, offset, .. } if mods.hold_off => {
+                Object::Note { column, offset }
+            }
+            v => v,
+        })
+        .collect::<Vec<_>>();
+    // osu!lazer orders objects by time when converting the beatmap, then sorts them again
+    // with the unstable sort, which decides the order within a chord.
+    objects.sort_by_key(|v| v.offset());
+    legacy_sort::sort(&mut objects, |a, b| a.offset().cmp(&b.offset()));
+
+    let mut strain = Strain::new(column_count);
+    let mut peaks = vec![];
+    let mut section_peak = 0.0f64;
+    let mut section_end = 0.0;
+    // The first object is only the previous object of the second one.
+    for (idx, pair) in objects.windows(2).enumerate() {
+        let (previous, current) = (&pair[0], &pair[1]);
+        let start = current.offset() as f64 / mods.rate;
+        let end = current.end_offset().unwrap_or(current.offset()) as f64 / mods.rate;
+        let previous_start = previous.offset() as f64 / mods.rate;
+
+        if idx == 0 {
+            section_end = (start / SECTION_LENGTH).ceil() * SECTION_LENGTH;
+        }
+        while start > section_end {
+            peaks.push(section_peak);
+            section_peak = strain.initial_strain(section_end, previous_start);
+            section_end += SECTION_LENGTH;
+        }
+        let value = strain.strain_value_of(
+            current.column() as usize,
+            start,
+            end,
+            start - previous_start,
+        );
+        section_peak = section_peak.max(value);
+    }
+    if objects.len() > 1 {
+        peaks.push(section_peak);
+    }
+    peaks
+}
+
+/// The star rating of osu!mania.
+pub fn star_rating(beatmap: &Beatmap, mods: &ManiaMods) -> f64 {
+    let mut peaks = strain_peaks(beatmap, mods)
+        .into_iter()
+        .filter(|v| *v > 0.0)
+        .collect::<Vec<_>>();
+    peaks.sort_by(|a, b| b.total_cmp(a));
+    let mut difficulty = 0.0;
+    let mut weight = 1.0;
+    for peak in peaks {
+        difficulty += peak * weight;
+        weight *= DECAY_WEIGHT;
+    }
+    difficulty * STAR_SCALING_FACTOR
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn star_rating_two_notes() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 0,
+            },
+            Object::LongNote {
+                column: 1,
+                offset: 1000,
+                end_offset: 1500,
+            },
+        ];
+        // The individual strain is 2, and the overall strain is decayed from 1 for 1 s plus 1.
+        let expected = (2.0 + 0.3 + 1.0) * STAR_SCALING_FACTOR;
+        let mods = ManiaMods::default();
+        assert!((star_rating(&beatmap, &mods) - expected).abs() < 1e-9);
+
+        let mods = ManiaMods {
+            rate: 2.0,
+            hold_off: true,
+        };
+        let expected = (2.0 + 0.3f64.sqrt() + 1.0) * STAR_SCALING_FACTOR;
+        assert!((star_rating(&beatmap, &mods) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn legacy_sort_chords() {
+        // Chords of 4 at every 100 ms, and a note, in column order.
+        let mut keys = (0..4)
+            .flat_map(|time| (0..4).map(move |column| (time * 100, column)))
+            .chain([(400, 0)])
+            .collect::<Vec<_>>();
+        legacy_sort::sort(&mut keys, |a, b| a.0.cmp(&b.0));
+        // Partitioning shuffles the chords after the pivot.
+        assert_eq!(
+            keys.iter().map(|v| v.1).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 0, 1, 2, 3, 3, 2, 0, 1, 3, 0, 1, 2, 0]
+        );
+        assert!(keys.windows(2).all(|v| v[0].0 <= v[1].0));
+
+        // Short slices are sorted by insertion, which is stable.
+        let mut keys = [(1, 0), (0, 1), (1, 2), (0, 3)];
+        legacy_sort::sort(&mut keys, |a, b| a.0.cmp(&b.0));
+        assert_eq!(keys, [(0, 1), (0, 3), (1, 0), (1, 2)]);
+
+        let mut keys = (0..1000).map(|v| (v * 7919 % 101, v)).collect::<Vec<_>>();
+        legacy_sort::sort(&mut keys, |a, b| a.0.cmp(&b.0));
+        assert!(keys.windows(2).all(|v| v[0].0 <= v[1].0));
+    }
+
+    #[test]
+    fn star_rating_fixture() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 0,
+            },
+            // Held under the chord at 300 ms, so the chord is 1.25 times harder.
+            Object::LongNote {
+                column: 0,
+                offset: 100,
+                end_offset: 1000,
+            },
+            Object::Note {
+                column: 1,
+                offset: 300,
+            },
+            Object::Note {
+                column: 2,
+                offset: 300,
+            },
+            // Released 100 ms after the long note in column 0, which adds to the overall strain.
+            Object::LongNote {
+                column: 3,
+                offset: 900,
+                end_offset: 1100,
+            },
+            // After an empty section.
+            Object::Note {
+                column: 0,
+                offset: 1500,
+            },
+        ];
+        // Worked out step by step from the formulas of the strain skill of osu!lazer,
+        // not measured in the game.
+        let expected_peaks = [
+            6.482848387532572,
+            5.561697519806982,
+            5.934044783746929,
+            5.019164903684846,
+        ];
+        let peaks = strain_peaks(&beatmap, &ManiaMods::default());
+        assert_eq!(peaks.len(), expected_peaks.len());
+        for (peak, expected) in peaks.iter().zip(expected_peaks) {
+            assert!((peak - expected).abs() < 1e-6);
+        }
+        assert!((star_rating(&beatmap, &ManiaMods::default()) - 0.359773828).abs() < 1e-6);
+    }
+
+    #[test]
+    fn star_rating_rate_and_hold_off() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        for idx in 0..200 {
+            let offset = idx * 150;
+            beatmap.objects.push(match idx % 3 {
+                0 => Object::LongNote {
+                    column: idx as u32 % 4,
+                    offset,
+                    end_offset: offset + 400,
+                },
+                _ => Object::Note {
+                    column: idx as u32 % 4,
+                    offset,
+                },
+            });
+        }
+        let nomod = star_rating(&beatmap, &ManiaMods::default());
+        let double_time = star_rating(
+            &beatmap,
+            &ManiaMods {
+                rate: 1.5,
+                ..Default::default()
+            },
+        );
+        let hold_off = star_rating(
+            &beatmap,
+            &ManiaMods {
+                hold_off: true,
+                ..Default::default()
+            },
+        );
+        assert!(double_time > nomod);
+        assert!(hold_off < nomod);
+    }
+}
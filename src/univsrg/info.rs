@@ -0,0 +1,219 @@
+//! Statistics of beatmaps, for looking inside a package without converting it.
+
+use serde::Serialize;
+
+use super::{
+    difficulty::{
+        etterna::{skillset_ratings, Skillset},
+        osu_mania::{star_rating, ManiaMods},
+    },
+    resource::ResourceEntry,
+    types::{Beatmap, Object},
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceInfo {
+    pub kind: &'static str,
+    pub path: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BpmInfo {
+    pub min: f32,
+    pub max: f32,
+    /// The BPM lasting longest.
+    pub main: f32,
+}
+
+/// Notes per second.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct NpsInfo {
+    /// The most notes in a second.
+    pub peak: f32,
+    pub average: f32,
+}
+
+/// Skillset ratings at 1.0x, in the style of Etterna.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MsdInfo {
+    pub overall: f32,
+    pub stream: f32,
+    pub jumpstream: f32,
+    pub handstream: f32,
+    pub stamina: f32,
+    pub jackspeed: f32,
+    pub chordjack: f32,
+    pub technical: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BeatmapInfo {
+    pub title: Option<String>,
+    pub title_unicode: Option<String>,
+    pub artist: Option<String>,
+    pub artist_unicode: Option<String>,
+    pub version: Option<String>,
+    pub creator: Option<String>,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+    pub key_count: Option<u32>,
+    /// From the first object to the end of the last one in ms.
+    pub length: i32,
+    pub note_count: usize,
+    pub long_note_count: usize,
+    pub bpm: Option<BpmInfo>,
+    pub nps: NpsInfo,
+    /// Effect time points changing velocity.
+    pub sv_count: usize,
+    pub star_rating: f64,
+    /// Only for 4K.
+    pub msd: Option<MsdInfo>,
+    pub resources: Vec<ResourceInfo>,
+}
+
+fn resource_info(kind: &'static str, entry: &ResourceEntry) -> ResourceInfo {
+    ResourceInfo {
+        kind,
+        path: entry.original_path.to_string_lossy().into_owned(),
+        size: entry.bytes.len(),
+    }
+}
+
+fn nps(beatmap: &Beatmap, length: i32) -> NpsInfo {
+    let mut offsets = beatmap
+        .objects
+        .iter()
+        .map(|v| v.offset())
+        .collect::<Vec<_>>();
+    offsets.sort();
+    // The most objects within 1 s, found with two pointers.
+    let mut peak = 0;
+    let mut begin = 0;
+    for (end, offset) in offsets.iter().enumerate() {
+        while offset - offsets[begin] >= 1000 {
+            begin += 1;
+        }
+        peak = peak.max(end + 1 - begin);
+    }
+    NpsInfo {
+        peak: peak as f32,
+        average: offsets.len() as f32 * 1000.0 / length.max(1000) as f32,
+    }
+}
+
+/// Collect the statistics of `beatmap`.
+pub fn beatmap_info(beatmap: &Beatmap) -> BeatmapInfo {
+    let first = beatmap.objects.iter().map(|v| v.offset()).min();
+    let length = match (first, beatmap.end_offset()) {
+        (Some(first), Some(end)) => end - first,
+        _ => 0,
+    };
+    let long_note_count = beatmap
+        .objects
+        .iter()
+        .filter(|v| matches!(v, Object::LongNote { .. }))
+        .count();
+    let bpm = beatmap.main_bpm().map(|main| {
+        let bpms = beatmap.bpm_time_points.iter().map(|v| v.bpm);
+        BpmInfo {
+            min: bpms.clone().fold(f32::INFINITY, f32::min),
+            max: bpms.fold(f32::NEG_INFINITY, f32::max),
+            main,
+        }
+    });
+    let msd = skillset_ratings(beatmap, 1.0).ok().map(|v| MsdInfo {
+        overall: v.overall,
+        stream: v.get(Skillset::Stream),
+        jumpstream: v.get(Skillset::Jumpstream),
+        handstream: v.get(Skillset::Handstream),
+        stamina: v.get(Skillset::Stamina),
+        jackspeed: v.get(Skillset::JackSpeed),
+        chordjack: v.get(Skillset::Chordjack),
+        technical: v.get(Skillset::Technical),
+    });
+
+    let mut resources = vec![];
+    if let Some(audio) = &beatmap.audio {
+        resources.push(resource_info("audio", audio));
+    }
+    if let Some(background) = &beatmap.background {
+        resources.push(resource_info("background", background));
+    }
+    if let Some(video) = &beatmap.video {
+        resources.push(resource_info("video", &video.resource));
+    }
+    if let Some(script) = &beatmap.storyboard.script {
+        resources.push(resource_info("storyboard", script));
+    }
+    for resource in &beatmap.storyboard.resources {
+        resources.push(resource_info("storyboard_file", resource));
+    }
+
+    BeatmapInfo {
+        title: beatmap.title.latin.clone(),
+        title_unicode: beatmap.title.unicode.clone(),
+        artist: beatmap.artist.latin.clone(),
+        artist_unicode: beatmap.artist.unicode.clone(),
+        version: beatmap.version.clone(),
+        creator: beatmap.creator.clone(),
+        source: beatmap.source.clone(),
+        tags: beatmap.tags.clone(),
+        key_count: beatmap.column_count,
+        length,
+        note_count: beatmap.objects.len() - long_note_count,
+        long_note_count,
+        bpm,
+        nps: nps(beatmap, length),
+        sv_count: beatmap
+            .effect_time_points
+            .iter()
+            .filter(|v| v.velocity_multiplier != 1.0)
+            .count(),
+        star_rating: star_rating(beatmap, &ManiaMods::default()),
+        msd,
+        resources,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, EffectTimePoint, TimeSignature};
+
+    #[test]
+    fn beatmap_info_statistics() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(7);
+        for (offset, bpm) in [(0, 150.0), (1000, 200.0), (3000, 150.0)] {
+            beatmap.bpm_time_points.push(BpmTimePoint {
+                offset,
+                bpm,
+                time_signature: TimeSignature::default(),
+            });
+        }
+        beatmap.effect_time_points =
+            vec![EffectTimePoint::new(0, 1.0), EffectTimePoint::new(500, 0.5)];
+        for idx in 0..20 {
+            beatmap.objects.push(Object::Note {
+                column: idx % 7,
+                offset: 1000 + idx as i32 * 100,
+            });
+        }
+        beatmap.objects.push(Object::LongNote {
+            column: 0,
+            offset: 3000,
+            end_offset: 5000,
+        });
+
+        let info = beatmap_info(&beatmap);
+        assert_eq!(info.length, 4000);
+        assert_eq!((info.note_count, info.long_note_count), (20, 1));
+        let bpm = info.bpm.unwrap();
+        assert_eq!((bpm.min, bpm.max, bpm.main), (150.0, 200.0, 150.0));
+        assert_eq!(info.nps.peak, 10.0);
+        assert_eq!(info.nps.average, 21.0 / 4.0);
+        assert_eq!(info.sv_count, 1);
+        assert!(info.msd.is_none());
+    }
+}
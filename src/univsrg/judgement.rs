@@ -0,0 +1,226 @@
+//! Hit windows of VSRGs and the mapping between their judgement difficulties.
+//!
+//! Judgements are named after osu!mania. Games with fewer judgements repeat the next larger window,
+//! e.g. BMS has no OK and Meh, so both of them are the window of BAD.
+
+/// Maximum absolute error in milliseconds for each judgement.
+/// Notes hit later than `miss` are missed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitWindows {
+    /// MAX in osu!mania, Marvelous in Quaver and Etterna, PGREAT in BMS.
+    pub perfect: f32,
+    /// 300 in osu!mania, Perfect in Quaver and Etterna, GREAT in BMS.
+    pub great: f32,
+    /// 200 in osu!mania, Great in Quaver and Etterna, GOOD in BMS.
+    pub good: f32,
+    /// 100 in osu!mania, Good in Quaver and Etterna, BAD in BMS.
+    pub ok: f32,
+    /// 50 in osu!mania, Okay in Quaver, Boo in Etterna, BAD in BMS.
+    pub meh: f32,
+    pub miss: f32,
+}
+
+/// Judgement presets of Quaver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuaverJudge {
+    Peaceful,
+    Lenient,
+    Chill,
+    Standard,
+    Strict,
+    Tough,
+    Extreme,
+}
+
+impl QuaverJudge {
+    pub const ALL: [QuaverJudge; 7] = [
+        QuaverJudge::Peaceful,
+        QuaverJudge::Lenient,
+        QuaverJudge::Chill,
+        QuaverJudge::Standard,
+        QuaverJudge::Strict,
+        QuaverJudge::Tough,
+        QuaverJudge::Extreme,
+    ];
+}
+
+/// `#RANK` of BMS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BmsRank {
+    VeryHard,
+    Hard,
+    Normal,
+    Easy,
+}
+
+impl BmsRank {
+    pub const ALL: [BmsRank; 4] = [
+        BmsRank::VeryHard,
+        BmsRank::Hard,
+        BmsRank::Normal,
+        BmsRank::Easy,
+    ];
+
+    /// The value of `#RANK`.
+    pub fn rank(&self) -> i32 {
+        match self {
+            BmsRank::VeryHard => 0,
+            BmsRank::Hard => 1,
+            BmsRank::Normal => 2,
+            BmsRank::Easy => 3,
+        }
+    }
+    pub fn from_rank(rank: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.rank() == rank)
+    }
+}
+
+/// Etterna judges range from J1 to J9, and J4 is the default.
+pub const ETTERNA_JUDGES: std::ops::RangeInclusive<u32> = 1..=9;
+
+impl HitWindows {
+    fn new(windows: [f32; 6]) -> Self {
+        Self {
+            perfect: windows[0],
+            great: windows[1],
+            good: windows[2],
+            ok: windows[3],
+            meh: windows[4],
+            miss: windows[5],
+        }
+    }
+
+    /// Hit windows of osu!mania for an overall difficulty.
+    /// https://osu.ppy.sh/wiki/en/Gameplay/Judgement/osu%21mania
+    pub fn from_osu_od(od: f32) -> Self {
+        let od = od.clamp(0.0, 10.0);
+        Self::new([
+            16.0,
+            64.0 - 3.0 * od,
+            97.0 - 3.0 * od,
+            127.0 - 3.0 * od,
+            151.0 - 3.0 * od,
+            188.0 - 3.0 * od,
+        ])
+    }
+    /// The overall difficulty of osu!mania closest to the hit windows, rounded to 0.1.
+    pub fn to_osu_od(&self) -> f32 {
+        // MAX is fixed, and 300 and 200 shrink by 3 ms per OD,
+        // so the average minimizes the distance used by the other games.
+        let windows = [self.great, self.good];
+        let bases = [64.0, 97.0];
+        let od = windows
+            .iter()
+            .zip(bases.iter())
+            .map(|(window, base)| (base - window) / 3.0)
+            .sum::<f32>()
+            / windows.len() as f32;
+        (od.clamp(0.0, 10.0) * 10.0).round() / 10.0
+    }
+
+    pub fn from_quaver(judge: QuaverJudge) -> Self {
+        Self::new(match judge {
+            QuaverJudge::Peaceful => [23.0, 57.0, 101.0, 141.0, 169.0, 218.0],
+            QuaverJudge::Lenient => [21.0, 52.0, 91.0, 128.0, 153.0, 198.0],
+            QuaverJudge::Chill => [19.0, 47.0, 83.0, 116.0, 140.0, 181.0],
+            QuaverJudge::Standard => [18.0, 43.0, 76.0, 106.0, 127.0, 164.0],
+            QuaverJudge::Strict => [16.0, 39.0, 69.0, 97.0, 116.0, 150.0],
+            QuaverJudge::Tough => [14.0, 35.0, 62.0, 87.0, 104.0, 134.0],
+            QuaverJudge::Extreme => [13.0, 33.0, 58.0, 80.0, 96.0, 124.0],
+        })
+    }
+    pub fn to_quaver(&self) -> QuaverJudge {
+        self.closest(QuaverJudge::ALL, Self::from_quaver)
+    }
+
+    /// Hit windows of Etterna for a judge from J1 to J9.
+    pub fn from_etterna(judge: u32) -> Self {
+        const SCALES: [f32; 9] = [1.50, 1.33, 1.16, 1.00, 0.84, 0.66, 0.50, 0.33, 0.20];
+        let scale = SCALES[(judge.clamp(1, 9) - 1) as usize];
+        // Boo is at most 180 ms, and anything later is a miss.
+        let boo = (180.0 * scale).min(180.0);
+        Self::new([
+            22.5 * scale,
+            45.0 * scale,
+            90.0 * scale,
+            135.0 * scale,
+            boo,
+            boo,
+        ])
+    }
+    pub fn to_etterna(&self) -> u32 {
+        self.closest(ETTERNA_JUDGES, Self::from_etterna)
+    }
+
+    /// Hit windows of LR2 for a `#RANK`.
+    pub fn from_bms(rank: BmsRank) -> Self {
+        let [pgreat, great, good] = match rank {
+            BmsRank::VeryHard => [8.0, 24.0, 40.0],
+            BmsRank::Hard => [15.0, 30.0, 60.0],
+            BmsRank::Normal => [18.0, 40.0, 100.0],
+            BmsRank::Easy => [21.0, 60.0, 120.0],
+        };
+        Self::new([pgreat, great, good, 200.0, 200.0, 200.0])
+    }
+    pub fn to_bms(&self) -> BmsRank {
+        self.closest(BmsRank::ALL, Self::from_bms)
+    }
+
+    /// Compare the three strictest windows, which every game has.
+    fn distance(&self, other: &Self) -> f32 {
+        (self.perfect - other.perfect).powi(2)
+            + (self.great - other.great).powi(2)
+            + (self.good - other.good).powi(2)
+    }
+    fn closest<T: Copy>(
+        &self,
+        candidates: impl IntoIterator<Item = T>,
+        f: impl Fn(T) -> Self,
+    ) -> T {
+        candidates
+            .into_iter()
+            .min_by(|a, b| self.distance(&f(*a)).total_cmp(&self.distance(&f(*b))))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn osu_od_round_trip() {
+        for od in [0.0, 5.0, 7.5, 8.0, 10.0] {
+            assert_eq!(HitWindows::from_osu_od(od).to_osu_od(), od);
+        }
+        assert_eq!(HitWindows::from_osu_od(8.0).great, 40.0);
+    }
+
+    #[test]
+    fn preset_round_trip() {
+        for judge in QuaverJudge::ALL {
+            assert_eq!(HitWindows::from_quaver(judge).to_quaver(), judge);
+        }
+        for judge in ETTERNA_JUDGES {
+            assert_eq!(HitWindows::from_etterna(judge).to_etterna(), judge);
+        }
+        for rank in BmsRank::ALL {
+            assert_eq!(HitWindows::from_bms(rank).to_bms(), rank);
+        }
+    }
+
+    #[test]
+    fn closest_between_games() {
+        assert_eq!(HitWindows::from_etterna(4).to_osu_od(), 4.3);
+        assert_eq!(HitWindows::from_osu_od(8.0).to_etterna(), 5);
+        assert_eq!(
+            HitWindows::from_bms(BmsRank::Normal).to_quaver(),
+            QuaverJudge::Lenient
+        );
+        // NaN difficulties give some preset instead of a panic.
+        let windows = HitWindows::from_osu_od(f32::NAN);
+        windows.to_quaver();
+        windows.to_etterna();
+        windows.to_bms();
+    }
+}
@@ -1,5 +1,16 @@
+pub mod audio;
+pub mod diff;
+pub mod difficulty;
+pub mod info;
+pub mod judgement;
+pub mod pattern;
+pub mod render;
 pub mod resource;
+pub mod simulate;
 pub mod traits;
+pub mod transform;
 pub mod types;
+pub mod validate;
+pub mod viewer;
 
 pub mod osu;
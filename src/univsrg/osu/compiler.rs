@@ -1,17 +1,22 @@
 use std::{
     fs::File,
     io::{self, Error, ErrorKind, Write},
+    num::NonZeroU32,
     path::{Path, PathBuf},
 };
 
 use osu_file_parser::{
     difficulty::{CircleSize, Difficulty, HPDrainRate, OverallDifficulty},
-    events::{Background, Event},
-    general::{AudioFilename, AudioLeadIn, Countdown, General, Mode, PreviewTime},
+    events::{Background, Break, Event, Video},
+    general::{AudioFilename, AudioLeadIn, Countdown, General, Mode, PreviewTime, SpecialStyle},
     hitobjects::{HitObject, HitObjectParams::OsuManiaHold, HitSample},
-    metadata::{Artist, ArtistUnicode, Creator, Metadata, Title, TitleUnicode, Version},
-    timingpoints::{Effects, SampleIndex, SampleSet, TimingPoint, Volume},
+    metadata::{
+        Artist, ArtistUnicode, BeatmapID, BeatmapSetID, Creator, Metadata, Source, Tags, Title,
+        TitleUnicode, Version,
+    },
+    timingpoints::{Effects, SampleIndex, SampleSet as OsuSampleSet, TimingPoint, Volume},
     Decimal, Events, FilePath, HitObjects, OsuFile, TimingPoints, VersionedDefault,
+    VersionedFromStr,
 };
 use tempfile::{tempdir, TempDir};
 use walkdir::WalkDir;
@@ -21,13 +26,20 @@ use super::super::{
     resource::ResourceOut,
     traits::ToOsu,
     types::{
-        Beatmap,
+        Beatmap, EffectTimePoint, Game,
         Object::{LongNote, Note},
-        Package,
+        Package, SampleSet,
     },
 };
+use super::header::insert_header;
 
-fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io::Result<()> {
+/// Returns warnings about what osu! cannot represent exactly.
+fn compile_beatmap(
+    beatmap: &Beatmap,
+    root: &Path,
+    resource: &ResourceOut,
+) -> io::Result<Vec<String>> {
+    let mut warnings = vec![];
     // Refuse to compile if column count or audio is None.
     if beatmap.column_count.is_none() {
         return Err(Error::new(
@@ -77,8 +89,25 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
         .version
         .as_ref()
         .map(|v| Version::from(v.clone()));
-    // source, tags, beatmap_id, beatmap_set_id
-    // are not supported.
+    metadata.source = beatmap //
+        .source
+        .as_ref()
+        .map(|v| Source::from(v.clone()));
+    if !beatmap.tags.is_empty() {
+        metadata.tags = Some(Tags::from(beatmap.tags.clone()));
+    }
+    if let Some(id) = beatmap.online_ids.get(&Game::Osu) {
+        metadata.beatmap_id = id
+            .beatmap_id
+            .and_then(|v| i32::try_from(v).ok())
+            .map(BeatmapID::from);
+        metadata.beatmap_set_id = id
+            .beatmap_set_id
+            .and_then(|v| i32::try_from(v).ok())
+            .map(BeatmapSetID::from);
+    }
+    // Genre, language, preview duration, custom fields and IDs of other games
+    // are written in the header. See `header.rs`.
     osu_file.metadata = Some(metadata);
 
     let mut difficulty = Difficulty::new();
@@ -90,8 +119,10 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
         .hp_difficulty
         .as_ref()
         .map(|v| HPDrainRate::from(Decimal::new_from_str(&format!("{:.1}", v))));
+    // Fall back to the closest overall difficulty if the beatmap is not from osu!.
     difficulty.overall_difficulty = beatmap
         .acc_difficulty
+        .or(beatmap.hit_windows.map(|v| v.to_osu_od()))
         .as_ref()
         .map(|v| OverallDifficulty::from(Decimal::new_from_str(&format!("{:.1}", v))));
     osu_file.difficulty = Some(difficulty);
@@ -101,45 +132,99 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
     general.audio_filename = resource
         .get_path_from_entry(beatmap.audio.as_ref().unwrap())
         .map(|v| AudioFilename::from(v.clone()));
-    general.audio_lead_in = beatmap.audio_lead_in.map(|v| AudioLeadIn::from(v));
-    general.preview_time = beatmap.preview_time.map(|v| PreviewTime::from(v));
+    general.audio_lead_in = beatmap.audio_lead_in.map(AudioLeadIn::from);
+    general.preview_time = beatmap.preview_time.map(PreviewTime::from);
     // audio_hash
     // are not supported.
     // Count down is not "No Count Down" by default, so we turn it off manually.
     general.countdown = Some(Countdown::NoCountdown);
+    // SpecialStyle puts the scratch at the left. Other layouts are written in the header.
+    if beatmap.layout.as_ref().is_some_and(|v| v.is_scratch(0)) {
+        general.special_style = Some(SpecialStyle::from(true));
+    }
     osu_file.general = Some(general);
 
+    fn to_osu_sample_set(sample_set: SampleSet) -> OsuSampleSet {
+        match sample_set {
+            SampleSet::Default => OsuSampleSet::BeatmapDefault,
+            SampleSet::Normal => OsuSampleSet::Normal,
+            SampleSet::Soft => OsuSampleSet::Soft,
+            SampleSet::Drum => OsuSampleSet::Drum,
+        }
+    }
+    fn to_osu_sample_index(sample_index: u32) -> SampleIndex {
+        NonZeroU32::new(sample_index)
+            .map(SampleIndex::Index)
+            .unwrap_or(SampleIndex::OsuDefaultHitsounds)
+    }
+    fn to_osu_volume(volume: u8) -> Volume {
+        Volume::new(volume.min(100) as i32, 14).unwrap()
+    }
     let mut timing_points = Vec::<TimingPoint>::new();
     let mut idx_red = 0;
     let mut idx_green = 0;
+    // Red lines carry the effects of the last effect time point.
+    let default_etp = EffectTimePoint::new(0, 1.0);
+    let mut last_etp = &default_etp;
     while idx_red < beatmap.bpm_time_points.len() || idx_green < beatmap.effect_time_points.len() {
-        if idx_red >= beatmap.bpm_time_points.len()
-            || beatmap.effect_time_points[idx_green].offset
-                < beatmap.bpm_time_points[idx_red].offset
+        if idx_green < beatmap.effect_time_points.len()
+            && (idx_red >= beatmap.bpm_time_points.len()
+                || beatmap.effect_time_points[idx_green].offset
+                    < beatmap.bpm_time_points[idx_red].offset)
         {
             let etp = &beatmap.effect_time_points[idx_green];
             let tp = TimingPoint::new_inherited(
                 etp.offset,
-                rust_decimal::Decimal::try_from(etp.velocity_multiplier).unwrap(),
+                rust_decimal::Decimal::try_from(etp.velocity_multiplier).map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "Velocity {} at {} ms is not a number.",
+                            etp.velocity_multiplier, etp.offset
+                        ),
+                    )
+                })?,
                 0, // Ignored by inherited timing points.
-                SampleSet::BeatmapDefault,
-                SampleIndex::OsuDefaultHitsounds,
-                Volume::new(100, 14).unwrap(),
-                Effects::new(false, false),
+                to_osu_sample_set(etp.sample_set),
+                to_osu_sample_index(etp.sample_index),
+                to_osu_volume(etp.volume),
+                Effects::new(etp.kiai, etp.omit_first_barline),
             );
             timing_points.push(tp);
+            last_etp = etp;
             idx_green += 1;
         } else {
             let btp = &beatmap.bpm_time_points[idx_red];
             let beat_duration_ms = 60000f32 / btp.bpm;
+            // osu! only supports a whole number of beats per bar.
+            let meter = btp.time_signature.closest_meter();
+            if !btp.time_signature.is_whole_meter() {
+                warnings.push(format!(
+                    "Time signature {}/{} at {} ms is approximated as {}/4.",
+                    btp.time_signature.numerator, btp.time_signature.denominator, btp.offset, meter
+                ));
+            }
+            // An effect time point at the same time is merged into the red line.
+            // It is still written as a green line if it changes the velocity.
+            let same_etp = beatmap
+                .effect_time_points
+                .get(idx_green)
+                .filter(|v| v.offset == btp.offset);
+            let omit_first_barline = same_etp.is_some_and(|v| v.omit_first_barline);
+            if let Some(etp) = same_etp {
+                last_etp = etp;
+                if etp.velocity_multiplier == 1.0 {
+                    idx_green += 1;
+                }
+            }
             let tp = TimingPoint::new_uninherited(
                 btp.offset,
                 Decimal::new_from_str(&format!("{:.3}", beat_duration_ms)),
-                btp.beats_per_bar as i32,
-                SampleSet::BeatmapDefault,
-                SampleIndex::OsuDefaultHitsounds,
-                Volume::new(100, 14).unwrap(),
-                Effects::new(false, false),
+                meter as i32,
+                to_osu_sample_set(last_etp.sample_set),
+                to_osu_sample_index(last_etp.sample_index),
+                to_osu_volume(last_etp.volume),
+                Effects::new(last_etp.kiai, omit_first_barline),
             );
             timing_points.push(tp);
             idx_red += 1;
@@ -165,7 +250,7 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
                     *column,
                     beatmap.column_count.unwrap(),
                 ) as i32);
-                ho.time = Decimal::from(*offset as i32);
+                ho.time = Decimal::from(*offset);
             }
             LongNote {
                 column,
@@ -177,7 +262,7 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
                     *column,
                     beatmap.column_count.unwrap(),
                 ) as i32);
-                ho.time = Decimal::from(*offset as i32);
+                ho.time = Decimal::from(*offset);
                 ho.obj_params = OsuManiaHold {
                     end_time: Decimal::from(*end_offset),
                 };
@@ -190,24 +275,49 @@ fn compile_beatmap(beatmap: &Beatmap, root: &Path, resource: &ResourceOut) -> io
     osu_file.hitobjects = Some(HitObjects(hit_objects));
 
     let mut events = Vec::<Event>::new();
-    beatmap
+    if let Some(v) = beatmap
         .background
         .as_ref()
         .and_then(|b| resource.get_path_from_entry(b))
-        .map(|v| Background {
+    {
+        events.push(Event::Background(Background {
             start_time: 0,
             file_name: FilePath::from(v),
             position: None,
             commands: vec![],
-        })
-        .map(|v| {
-            events.push(Event::Background(v));
-        });
-    osu_file.events = Some(Events(events));
+        }));
+    }
+    if let Some((v, video)) = beatmap
+        .video
+        .as_ref()
+        .and_then(|v| resource.get_path_from_entry(&v.resource).map(|p| (p, v)))
+    {
+        events.push(Event::Video(Video::new(
+            video.offset,
+            FilePath::from(v),
+            None,
+        )));
+    }
+    for b in &beatmap.breaks {
+        events.push(Event::Break(Break::new(b.offset, b.end_offset)));
+    }
+    // Storyboard events are kept as osu! scripts, so parse them back.
+    // The `.osb` file and the images are in the resource pool, so they are already inflated.
+    if !beatmap.storyboard.events.is_empty() {
+        match Events::from_str(&beatmap.storyboard.events.join("\n"), osu_file.version) {
+            Ok(Some(storyboard_events)) => events.extend(storyboard_events.0),
+            _ => warnings.push("Failed to compile storyboard events, skip.".to_owned()),
+        }
+    }
+    // osu-file-parser rejects the empty line written for an empty section.
+    if !events.is_empty() {
+        osu_file.events = Some(Events(events));
+    }
 
-    File::create(out_file_path)?.write_all(osu_file.to_string().as_bytes())?;
+    let osu_file_string = insert_header(&osu_file.to_string(), beatmap);
+    File::create(out_file_path)?.write_all(osu_file_string.as_bytes())?;
 
-    Ok(())
+    Ok(warnings)
 }
 
 fn zip_folder<P: AsRef<Path>>(folder_path: P, zip_path: P) -> io::Result<()> {
@@ -226,9 +336,7 @@ fn zip_folder<P: AsRef<Path>>(folder_path: P, zip_path: P) -> io::Result<()> {
 
         if path.is_file() {
             let mut file = File::open(path)?;
-            let relative_path = path
-                .strip_prefix(folder_path)
-                .map_err(|e| io::Error::new(ErrorKind::Other, e))?;
+            let relative_path = path.strip_prefix(folder_path).map_err(io::Error::other)?;
             zip.start_file(relative_path.to_string_lossy(), options)?;
             io::copy(&mut file, &mut zip)?;
         }
@@ -240,7 +348,7 @@ fn zip_folder<P: AsRef<Path>>(folder_path: P, zip_path: P) -> io::Result<()> {
 }
 
 impl ToOsu for Package {
-    fn to_osu(&self, path: &Path) -> io::Result<()> {
+    fn to_osu(&self, path: &Path) -> io::Result<Vec<String>> {
         let temp_dir: TempDir = tempdir()?;
 
         // Remap and settle resources.
@@ -248,16 +356,94 @@ impl ToOsu for Package {
         resource_out.inflate(temp_dir.path().to_owned(), &self.resource_pool)?;
 
         // Compile beatmaps.
+        let mut warnings = vec![];
         for beatmap in &self.beatmaps {
             let result = compile_beatmap(beatmap, temp_dir.path(), &resource_out);
-            if result.is_err() {
-                continue;
+            let basename = beatmap.make_basename();
+            match result {
+                Ok(beatmap_warnings) => warnings.extend(
+                    beatmap_warnings
+                        .into_iter()
+                        .map(|v| format!("{}: {}", basename, v)),
+                ),
+                Err(e) => warnings.push(format!("{}: Skipped: {}", basename, e)),
             }
         }
 
         // Package all files to a bundle.
-        zip_folder(temp_dir.as_ref(), &path)?;
+        zip_folder(temp_dir.as_ref(), path)?;
+
+        Ok(warnings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::{
+        osu::types::OszPath,
+        resource::ResourceEntry,
+        traits::AppendToUnivsrg,
+        types::{BpmTimePoint, TimeSignature},
+    };
+
+    #[test]
+    fn compile_parse_round_trip() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("audio.mp3"), b"audio").unwrap();
+        let audio =
+            ResourceEntry::new_from_file_in_bundle(dir.path(), PathBuf::from("audio.mp3")).unwrap();
+
+        let mut beatmap = Beatmap::new();
+        beatmap.title.latin = Some("Title".to_owned());
+        beatmap.version = Some("Hard".to_owned());
+        beatmap.column_count = Some(4);
+        beatmap.audio = Some(audio.clone());
+        // Written in the header before the first section.
+        beatmap.genre = Some("Pop".to_owned());
+        beatmap.language = Some("Japanese".to_owned());
+        beatmap
+            .custom_fields
+            .insert("key: with colon".to_owned(), "a\nb".to_owned());
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects.push(Note {
+            column: 1,
+            offset: 500,
+        });
+        let mut package = Package::new();
+        package.resource_pool.insert(audio);
+        package.beatmaps.push(beatmap);
+
+        let osz_path = dir.path().join("out.osz");
+        package.to_osu(&osz_path).unwrap();
+        let mut parsed = Package::new();
+        OszPath(osz_path).append_to_univsrg(&mut parsed).unwrap();
+
+        // A rejected file would be skipped by the parser.
+        assert_eq!(parsed.beatmaps.len(), 1);
+        let beatmap = &parsed.beatmaps[0];
+        assert_eq!(beatmap.version.as_deref(), Some("Hard"));
+        assert_eq!(beatmap.genre.as_deref(), Some("Pop"));
+        assert_eq!(beatmap.language.as_deref(), Some("Japanese"));
+        assert_eq!(
+            beatmap
+                .custom_fields
+                .get("key: with colon")
+                .map(|v| v.as_str()),
+            Some("a\nb")
+        );
+        assert_eq!(beatmap.objects, package.beatmaps[0].objects);
 
-        Ok(())
+        // An infinite velocity skips the beatmap instead of a panic.
+        package.beatmaps[0]
+            .effect_time_points
+            .push(EffectTimePoint::new(0, f32::INFINITY));
+        let warnings = package.to_osu(&dir.path().join("broken.osz")).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("Skipped"));
     }
 }
@@ -0,0 +1,227 @@
+//! Beatmap fields without a counterpart in `.osu` files.
+//!
+//! They are written as comments between the version line and the first section,
+//! which osu! ignores, so that they survive an osz round trip:
+//!
+//! ```text
+//! osu file format v14
+//! // univsrg.genre: Pop
+//! // univsrg.online_id.malody: 123 456
+//! // univsrg.custom.key: value
+//! ```
+
+use super::super::types::{
+    Beatmap, Column, ColumnRole, Game, Layout, LayoutPreset, OnlineId, Side,
+};
+
+const PREFIX: &str = "// univsrg.";
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+}
+
+/// Keys contain no spaces so that the first `": "` separates the key and the value.
+fn escape_key(key: &str) -> String {
+    escape(key).replace(' ', "\\s")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('s') => result.push(' '),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Format a layout as its preset and one token per column, e.g. `custom SL KL K KR P`.
+fn format_layout(layout: &Layout) -> String {
+    let mut tokens = vec![layout.preset.name().to_owned()];
+    for column in &layout.columns {
+        let role = match column.role {
+            ColumnRole::Key => "K",
+            ColumnRole::Scratch => "S",
+            ColumnRole::Pedal => "P",
+        };
+        let side = match column.side {
+            Some(Side::Left) => "L",
+            Some(Side::Right) => "R",
+            None => "",
+        };
+        tokens.push(format!("{}{}", role, side));
+    }
+    tokens.join(" ")
+}
+
+fn parse_layout(value: &str) -> Option<Layout> {
+    let mut tokens = value.split(' ');
+    let preset = LayoutPreset::from_name(tokens.next()?)?;
+    let columns = tokens
+        .map(|token| {
+            let mut chars = token.chars();
+            let role = match chars.next()? {
+                'K' => ColumnRole::Key,
+                'S' => ColumnRole::Scratch,
+                'P' => ColumnRole::Pedal,
+                _ => return None,
+            };
+            let side = match chars.next() {
+                Some('L') => Some(Side::Left),
+                Some('R') => Some(Side::Right),
+                _ => None,
+            };
+            Some(Column { role, side })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Layout { preset, columns })
+}
+
+fn format_id(id: Option<i64>) -> String {
+    id.map(|v| v.to_string()).unwrap_or("-".to_owned())
+}
+
+/// Make the comment lines for `beatmap`.
+pub(crate) fn write_header(beatmap: &Beatmap) -> Vec<String> {
+    let mut fields = Vec::<(String, String)>::new();
+    if let Some(v) = &beatmap.genre {
+        fields.push(("genre".to_owned(), v.clone()));
+    }
+    if let Some(v) = &beatmap.language {
+        fields.push(("language".to_owned(), v.clone()));
+    }
+    if let Some(v) = beatmap.preview_duration {
+        fields.push(("preview_duration".to_owned(), v.to_string()));
+    }
+    // Layouts other than keys only and SpecialStyle are not supported by osu!.
+    if let Some(layout) = &beatmap.layout {
+        let column_count = layout.columns.len() as u32;
+        if *layout != Layout::new(LayoutPreset::Keys, column_count)
+            && *layout != Layout::new(LayoutPreset::ScratchLeft, column_count)
+        {
+            fields.push(("layout".to_owned(), format_layout(layout)));
+        }
+    }
+    let mut online_ids = beatmap.online_ids.iter().collect::<Vec<_>>();
+    online_ids.sort_by_key(|(game, _)| **game);
+    for (game, id) in online_ids {
+        // IDs of osu! are stored in the metadata section.
+        if *game == Game::Osu {
+            continue;
+        }
+        fields.push((
+            format!("online_id.{}", game.name()),
+            format!(
+                "{} {}",
+                format_id(id.beatmap_id),
+                format_id(id.beatmap_set_id)
+            ),
+        ));
+    }
+    for (key, value) in &beatmap.custom_fields {
+        fields.push((format!("custom.{}", escape_key(key)), value.clone()));
+    }
+
+    fields
+        .into_iter()
+        .map(|(key, value)| format!("{}{}: {}", PREFIX, key, escape(&value)))
+        .collect()
+}
+
+/// Insert the comment lines for `beatmap` after the version line of `osu_file_string`.
+pub(crate) fn insert_header(osu_file_string: &str, beatmap: &Beatmap) -> String {
+    let lines = write_header(beatmap);
+    if lines.is_empty() {
+        return osu_file_string.to_owned();
+    }
+    match osu_file_string.split_once('\n') {
+        Some((version_line, rest)) => {
+            format!("{}\n{}\n{}", version_line, lines.join("\n"), rest)
+        }
+        None => format!("{}\n{}\n", osu_file_string, lines.join("\n")),
+    }
+}
+
+/// Read the comment lines before the first section of `osu_file_string` into `beatmap`.
+pub(crate) fn read_header(osu_file_string: &str, beatmap: &mut Beatmap) {
+    for line in osu_file_string.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            break;
+        }
+        let Some((key, value)) = line.strip_prefix(PREFIX).and_then(|v| v.split_once(": ")) else {
+            continue;
+        };
+        let value = unescape(value);
+        match key {
+            "genre" => beatmap.genre = Some(value),
+            "language" => beatmap.language = Some(value),
+            "preview_duration" => beatmap.preview_duration = value.parse().ok(),
+            "layout" => beatmap.layout = parse_layout(&value),
+            _ => {
+                if let Some(game) = key.strip_prefix("online_id.").and_then(Game::from_name) {
+                    let mut ids = value.split(' ').map(|v| v.parse::<i64>().ok());
+                    beatmap.online_ids.insert(
+                        game,
+                        OnlineId {
+                            beatmap_id: ids.next().flatten(),
+                            beatmap_set_id: ids.next().flatten(),
+                        },
+                    );
+                } else if let Some(custom_key) = key.strip_prefix("custom.") {
+                    beatmap.custom_fields.insert(unescape(custom_key), value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let mut beatmap = Beatmap::new();
+        beatmap.genre = Some("Pop".to_owned());
+        beatmap.language = Some("Japanese".to_owned());
+        beatmap.preview_duration = Some(15000);
+        beatmap.online_ids.insert(
+            Game::Malody,
+            OnlineId {
+                beatmap_id: Some(123),
+                beatmap_set_id: None,
+            },
+        );
+        beatmap
+            .custom_fields
+            .insert("multi: line".to_owned(), "a\nb\\n".to_owned());
+        let mut layout = Layout::new(LayoutPreset::Custom, 8);
+        layout.columns[7].role = ColumnRole::Pedal;
+        beatmap.layout = Some(layout);
+
+        let osu_file_string = insert_header("osu file format v14\n\n[General]\n", &beatmap);
+        assert!(osu_file_string.starts_with("osu file format v14\n// univsrg."));
+
+        let mut parsed = Beatmap::new();
+        read_header(&osu_file_string, &mut parsed);
+        assert_eq!(parsed.genre, beatmap.genre);
+        assert_eq!(parsed.language, beatmap.language);
+        assert_eq!(parsed.preview_duration, beatmap.preview_duration);
+        assert_eq!(parsed.online_ids, beatmap.online_ids);
+        assert_eq!(parsed.custom_fields, beatmap.custom_fields);
+        assert_eq!(parsed.layout, beatmap.layout);
+    }
+}
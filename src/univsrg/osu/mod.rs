@@ -1,3 +1,5 @@
 pub mod compiler;
+mod header;
 pub mod parser;
+pub(crate) mod storyboard;
 pub mod types;
@@ -1,39 +1,54 @@
 use std::{
     fs::{create_dir_all, read_dir, File},
-    io::{self, ErrorKind, Read},
+    io::{self, Read},
     path::{Path, PathBuf},
 };
 
-use osu_file_parser::{events::Event, hitobjects::HitObjectParams, OsuFile, VersionedToString};
+use osu_file_parser::{
+    events::Event,
+    hitobjects::HitObjectParams,
+    timingpoints::{SampleIndex, SampleSet as OsuSampleSet},
+    FilePath, OsuFile, VersionedToString,
+};
 use rust_decimal::prelude::ToPrimitive;
 use tempfile::{tempdir, TempDir};
 use zip::ZipArchive;
 
 use super::{
     super::{
+        judgement::HitWindows,
         resource::ResourceEntry,
         traits::AppendToUnivsrg,
-        types::{Beatmap, BpmTimePoint, EffectTimePoint, Object, Package},
+        types::{
+            Beatmap, BpmTimePoint, BreakPeriod, EffectTimePoint, Game, Layout, LayoutPreset,
+            Object, OnlineId, Package, SampleSet, Storyboard, TimeSignature, Video,
+        },
     },
+    header::read_header,
+    storyboard::load_referenced_resources,
     types::OszPath,
 };
 
+/// Parse an osu file in `bundle_base`.
+/// `storyboard` is the storyboard shared by the beatmap set, i.e. the `.osb` file.
 fn parse_osu_file(
     osu_file_path: &Path,
     bundle_base: &Path,
+    storyboard: &Storyboard,
     package: &mut Package,
 ) -> io::Result<()> {
-    let mut file = File::open(&osu_file_path)?;
+    let mut file = File::open(osu_file_path)?;
     let mut osu_file_string = String::new();
     file.read_to_string(&mut osu_file_string)?;
-    let osu_file = osu_file_string.parse::<OsuFile>().unwrap();
+    let osu_file = osu_file_string
+        .parse::<OsuFile>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
     let osu_file_version: u8 = osu_file.version;
 
     let resource_pool = &mut package.resource_pool;
     let mut beatmap = Beatmap::new();
 
-    let metadata = osu_file.metadata.as_ref();
-    metadata.map(|m| {
+    if let Some(m) = osu_file.metadata.as_ref() {
         beatmap.title.latin = m.title.as_ref().and_then(|v| v.to_string(osu_file_version));
         beatmap.title.unicode = m
             .title_unicode
@@ -55,10 +70,38 @@ fn parse_osu_file(
             .version
             .as_ref()
             .and_then(|v| v.to_string(osu_file_version));
-    });
+        beatmap.source = m
+            .source
+            .as_ref()
+            .and_then(|v| v.to_string(osu_file_version))
+            .filter(|v| !v.is_empty());
+        beatmap.tags = m
+            .tags
+            .as_ref()
+            .map(|v| Vec::<String>::from(v.clone()))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|v| !v.is_empty())
+            .collect();
+        let id = OnlineId {
+            beatmap_id: m
+                .beatmap_id
+                .as_ref()
+                .map(|v| i32::from(v.clone()) as i64)
+                .filter(|v| *v > 0),
+            beatmap_set_id: m
+                .beatmap_set_id
+                .as_ref()
+                .map(|v| i32::from(v.clone()) as i64)
+                .filter(|v| *v > 0),
+        };
+        if id != OnlineId::default() {
+            beatmap.online_ids.insert(Game::Osu, id);
+        }
+    }
+    read_header(&osu_file_string, &mut beatmap);
 
-    let difficulty = osu_file.difficulty.as_ref();
-    difficulty.map(|d| {
+    if let Some(d) = osu_file.difficulty.as_ref() {
         beatmap.column_count = d
             .circle_size
             .as_ref()
@@ -74,14 +117,29 @@ fn parse_osu_file(
             .as_ref()
             .and_then(|v| v.to_string(osu_file_version))
             .and_then(|v| v.parse::<f32>().ok());
-    });
-    beatmap.column_count.ok_or(io::Error::new(
-        ErrorKind::Other,
-        "Column count is necessary.",
-    ))?;
-
-    let general = osu_file.general.as_ref();
-    general.map(|g| {
+        beatmap.hit_windows = beatmap.acc_difficulty.map(HitWindows::from_osu_od);
+    }
+    beatmap
+        .column_count
+        .ok_or(io::Error::other("Column count is necessary."))?;
+
+    if let Some(g) = osu_file.general.as_ref() {
+        // The layout in the header is preferred.
+        if beatmap.layout.is_none() {
+            let special_style = g
+                .special_style
+                .as_ref()
+                .and_then(|v| v.to_string(osu_file_version))
+                .is_some_and(|v| v == "1");
+            beatmap.layout = Some(Layout::new(
+                if special_style {
+                    LayoutPreset::ScratchLeft
+                } else {
+                    LayoutPreset::Keys
+                },
+                beatmap.column_count.unwrap(),
+            ));
+        }
         beatmap.preview_time = g
             .preview_time
             .as_ref()
@@ -99,46 +157,67 @@ fn parse_osu_file(
             .and_then(|v| {
                 ResourceEntry::new_from_file_in_bundle(bundle_base, PathBuf::from(v)).ok()
             })
-            .map(|v| {
+            .inspect(|v| {
                 resource_pool.insert(v.clone());
-                v
             });
-    });
+    }
 
-    let timing_points = osu_file.timing_points.as_ref();
-    timing_points.map(|t| &t.0).map(|t| {
+    if let Some(t) = osu_file.timing_points.as_ref().map(|t| &t.0) {
         let mut btps = Vec::<BpmTimePoint>::new();
         let mut etps = Vec::<EffectTimePoint>::new();
         for tp in t {
             let offset = tp.time().to_string().parse::<i32>().ok();
+            // Both red and green lines carry effects.
+            let with_effects = |offset: i32, velocity_multiplier: f32| {
+                let mut etp = EffectTimePoint::new(offset, velocity_multiplier);
+                if let Some(effects) = tp.effects() {
+                    etp.kiai = effects.kiai_time_enabled();
+                    etp.omit_first_barline = effects.no_first_barline_in_taiko_mania();
+                }
+                etp.volume = tp.volume().volume().clamp(0, 100) as u8;
+                etp.sample_set = match tp.sample_set() {
+                    OsuSampleSet::Normal => SampleSet::Normal,
+                    OsuSampleSet::Soft => SampleSet::Soft,
+                    OsuSampleSet::Drum => SampleSet::Drum,
+                    _ => SampleSet::Default,
+                };
+                etp.sample_index = match tp.sample_index() {
+                    SampleIndex::Index(v) => v.get(),
+                    _ => 0,
+                };
+                etp
+            };
             if tp.uninherited() {
                 let bpm = tp.calc_bpm().and_then(|v| v.to_f32());
-                let beats_per_bar = tp.meter() as u32;
+                // osu! meters are counted in quarter notes.
+                let time_signature = TimeSignature::new(tp.meter().max(1) as u32, 4)?;
                 if let (Some(offset), Some(bpm)) = (offset, bpm) {
                     btps.push(BpmTimePoint {
                         offset,
                         bpm,
-                        beats_per_bar,
+                        time_signature,
                     });
+                    // Red lines reset the velocity.
+                    etps.push(with_effects(offset, 1.0));
                 }
             } else {
                 let velocity_multiplier = tp
                     .calc_slider_velocity_multiplier()
                     .and_then(|v| v.to_f32());
                 if let (Some(offset), Some(velocity_multiplier)) = (offset, velocity_multiplier) {
-                    etps.push(EffectTimePoint {
-                        offset,
-                        velocity_multiplier,
-                    })
+                    // A green line overrides the red line at the same time.
+                    if etps.last().is_some_and(|v| v.offset == offset) {
+                        etps.pop();
+                    }
+                    etps.push(with_effects(offset, velocity_multiplier));
                 };
             }
         }
         beatmap.bpm_time_points = btps;
         beatmap.effect_time_points = etps;
-    });
+    }
 
-    let hit_objects = osu_file.hitobjects.as_ref();
-    hit_objects.map(|h| &h.0).map(|h| {
+    if let Some(h) = osu_file.hitobjects.as_ref().map(|h| &h.0) {
         let mut objects = Vec::<Object>::new();
         // https://osu.ppy.sh/wiki/en/Client/File_formats/osu_%28file_format%29#holds-(osu!mania-only)
         fn position_to_column(x: u32, column_count: u32) -> u32 {
@@ -152,46 +231,87 @@ fn parse_osu_file(
                 match &ho.obj_params {
                     HitObjectParams::HitCircle => objects.push(Object::Note { column, offset }),
                     HitObjectParams::OsuManiaHold { end_time } => {
-                        end_time.to_string().parse::<i32>().ok().map(|v| {
+                        if let Ok(v) = end_time.to_string().parse::<i32>() {
                             objects.push(Object::LongNote {
                                 column,
                                 offset,
                                 end_offset: v,
-                            })
-                        });
+                            });
+                        }
                     }
                     _ => {}
                 }
             }
         }
         beatmap.objects = objects;
-    });
+    }
 
-    let events = osu_file.events.as_ref();
-    events.map(|e| &e.0).map(|e| {
+    beatmap.storyboard = storyboard.clone();
+    if let Some(e) = osu_file.events.as_ref().map(|e| &e.0) {
+        fn file_name_to_path(file_name: &FilePath) -> Option<PathBuf> {
+            file_name
+                .get()
+                .to_str()
+                .map(|v| PathBuf::from(v.trim_matches('"').replace('\\', "/")))
+        }
         for event in e {
-            if let Event::Background(bg) = event {
-                let bg_file_name = bg
-                    .file_name
-                    .get()
-                    .to_str()
-                    .map(|v| v.trim_matches('"').to_owned());
-                if bg_file_name.is_none() {
-                    continue;
+            match event {
+                Event::Background(bg) => {
+                    if beatmap.background.is_some() {
+                        continue;
+                    }
+                    let Some(bg_file_name) = file_name_to_path(&bg.file_name) else {
+                        continue;
+                    };
+                    if let Ok(v) = ResourceEntry::new_from_file_in_bundle(bundle_base, bg_file_name)
+                    {
+                        resource_pool.insert(v.clone());
+                        beatmap.background = Some(v);
+                    }
+                }
+                Event::Video(video) => {
+                    if beatmap.video.is_some() {
+                        continue;
+                    }
+                    let Some(video_file_name) = file_name_to_path(&video.file_name) else {
+                        continue;
+                    };
+                    if let Ok(v) =
+                        ResourceEntry::new_from_file_in_bundle(bundle_base, video_file_name)
+                    {
+                        resource_pool.insert(v.clone());
+                        beatmap.video = Some(Video {
+                            resource: v,
+                            offset: video.start_time,
+                        });
+                    }
+                }
+                Event::Break(b) => beatmap.breaks.push(BreakPeriod {
+                    offset: b.start_time,
+                    end_offset: b.end_time,
+                }),
+                Event::Comment(_) => {}
+                // The rest are storyboard events.
+                _ => {
+                    if let Some(v) = event.to_string(osu_file_version) {
+                        beatmap.storyboard.events.push(v);
+                    }
                 }
-                ResourceEntry::new_from_file_in_bundle(
-                    bundle_base,
-                    PathBuf::from(bg_file_name.unwrap()),
-                )
-                .ok()
-                .map(|v| {
-                    resource_pool.insert(v.clone());
-                    beatmap.background = Some(v);
-                });
-                break;
             }
         }
-    });
+    }
+    if !beatmap.storyboard.events.is_empty() {
+        let resources = load_referenced_resources(
+            &beatmap.storyboard.events.join("\n"),
+            bundle_base,
+            resource_pool,
+        );
+        for v in resources {
+            if !beatmap.storyboard.resources.contains(&v) {
+                beatmap.storyboard.resources.push(v);
+            }
+        }
+    }
 
     package.beatmaps.push(beatmap);
 
@@ -225,17 +345,37 @@ impl AppendToUnivsrg for OszPath {
             }
         }
 
+        // Load the storyboard shared by all difficulties.
+        let mut storyboard = Storyboard::default();
+        for entry in read_dir(&source_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|v| v.to_str()) != Some("osb") {
+                continue;
+            }
+            let Ok(script) = ResourceEntry::new_from_file_in_bundle(
+                source_dir.path(),
+                PathBuf::from(path.file_name().unwrap()),
+            ) else {
+                continue;
+            };
+            package.resource_pool.insert(script.clone());
+            storyboard.resources = load_referenced_resources(
+                &String::from_utf8_lossy(&script.bytes),
+                source_dir.path(),
+                &mut package.resource_pool,
+            );
+            storyboard.script = Some(script);
+            // There is only one storyboard per beatmap set.
+            break;
+        }
+
         // Enumerate osu files and parse.
         for entry in read_dir(&source_dir)? {
             let path = entry?.path();
-            if !path
-                .extension()
-                .and_then(|v| v.to_str())
-                .is_some_and(|v| v == "osu")
-            {
+            if path.extension().and_then(|v| v.to_str()) != Some("osu") {
                 continue;
             }
-            let _ = parse_osu_file(&path, source_dir.path(), package);
+            let _ = parse_osu_file(&path, source_dir.path(), &storyboard, package);
         }
 
         Ok(())
@@ -0,0 +1,118 @@
+use std::path::{Path, PathBuf};
+
+use super::super::resource::{ResourceEntry, ResourcePool};
+
+/// Split a line by commas outside of quotes.
+fn split_fields(line: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut in_quotes = false;
+    let mut begin = 0;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                fields.push(&line[begin..idx]);
+                begin = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(&line[begin..]);
+    fields
+}
+
+fn to_path(field: &str) -> PathBuf {
+    // Storyboards made on Windows use backslashes.
+    PathBuf::from(field.trim().trim_matches('"').replace('\\', "/"))
+}
+
+/// Get the paths of the files used by a storyboard script,
+/// which is the content of an `.osb` file or the storyboard events of an `.osu` file.
+pub(crate) fn referenced_paths(script: &str) -> Vec<PathBuf> {
+    let mut variables = Vec::<(String, String)>::new();
+    let mut paths = Vec::<PathBuf>::new();
+    for line in script.lines() {
+        // Commands are indented and never refer to files.
+        if line.starts_with([' ', '_']) || line.starts_with("//") {
+            continue;
+        }
+        if let Some((name, value)) = line.strip_prefix('$').and_then(|v| v.split_once('=')) {
+            variables.push((format!("${}", name), value.trim().to_owned()));
+            continue;
+        }
+        let mut line = line.trim().to_owned();
+        for (name, value) in &variables {
+            line = line.replace(name, value);
+        }
+
+        let fields = split_fields(&line);
+        match fields[0] {
+            "Sprite" | "4" | "Sample" | "5" if fields.len() > 3 => {
+                paths.push(to_path(fields[3]));
+            }
+            "Animation" | "6" if fields.len() > 6 => {
+                // Frames are numbered before the extension, e.g. `star0.png`, `star1.png`.
+                let path = to_path(fields[3]);
+                let frame_count = fields[6].trim().parse::<u32>().unwrap_or(0);
+                let stem = path
+                    .file_stem()
+                    .map(|v| v.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                for idx in 0..frame_count {
+                    let mut frame_path = path.with_file_name(format!("{}{}", stem, idx));
+                    if let Some(extension) = path.extension() {
+                        frame_path.set_extension(extension);
+                    }
+                    paths.push(frame_path);
+                }
+            }
+            _ => {}
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Load the files used by a storyboard script into `resource_pool`.
+/// Missing files are skipped.
+pub(crate) fn load_referenced_resources(
+    script: &str,
+    bundle_base: &Path,
+    resource_pool: &mut ResourcePool,
+) -> Vec<ResourceEntry> {
+    referenced_paths(script)
+        .into_iter()
+        .filter_map(|path| ResourceEntry::new_from_file_in_bundle(bundle_base, path).ok())
+        .inspect(|v| {
+            resource_pool.insert(v.clone());
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn storyboard_referenced_paths() {
+        let script = "[Variables]\n\
+            $dir=sb\n\
+            [Events]\n\
+            //Storyboard Layer 0 (Background)\n\
+            Sprite,Background,Centre,\"$dir\\bg.png\",320,240\n\
+            \x20F,0,0,1000,0,1\n\
+            Animation,Foreground,Centre,\"sb/star, big.png\",320,240,2,100,LoopForever\n\
+            Sample,1000,0,\"hit.wav\",100\n\
+            4,0,1,\"sb\\bg.png\",0,0\n";
+        assert_eq!(
+            referenced_paths(script),
+            vec![
+                PathBuf::from("hit.wav"),
+                PathBuf::from("sb/bg.png"),
+                PathBuf::from("sb/star, big0.png"),
+                PathBuf::from("sb/star, big1.png"),
+            ]
+        );
+    }
+}
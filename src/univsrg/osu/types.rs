@@ -1,3 +1,3 @@
 use std::path::PathBuf;
 
-pub struct OszPath(pub(crate) PathBuf);
+pub struct OszPath(pub PathBuf);
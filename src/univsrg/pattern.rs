@@ -0,0 +1,372 @@
+//! Labeling the patterns of a beatmap over time.
+
+use super::types::{Beatmap, Object};
+
+/// Rows further apart than this in ms are not in the same pattern.
+const MAX_GAP: i32 = 1000;
+/// Releases closer than this in ms are at the same time.
+const RELEASE_TOLERANCE: i32 = 30;
+/// Rows before and after a row looked at to label it.
+const WINDOW: (usize, usize) = (3, 4);
+/// Runs of fewer rows are transitions, and take the label of a neighbor.
+const MIN_RUN: usize = WINDOW.0 + WINDOW.1 + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// Single notes without jacks.
+    Stream,
+    /// A stream with jumps.
+    Jumpstream,
+    /// A stream with chords of three or more.
+    Handstream,
+    /// Two columns alternating.
+    Trill,
+    /// Two jumps alternating.
+    Jumptrill,
+    /// Single notes repeated in a column.
+    Jack,
+    /// Chords repeated in some columns.
+    Chordjack,
+    /// A stream of jumps on adjacent columns.
+    Bracket,
+    /// Long notes released at different times while others are held.
+    LongNoteRelease,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 9] = [
+        Pattern::Stream,
+        Pattern::Jumpstream,
+        Pattern::Handstream,
+        Pattern::Trill,
+        Pattern::Jumptrill,
+        Pattern::Jack,
+        Pattern::Chordjack,
+        Pattern::Bracket,
+        Pattern::LongNoteRelease,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Stream => "stream",
+            Pattern::Jumpstream => "jumpstream",
+            Pattern::Handstream => "handstream",
+            Pattern::Trill => "trill",
+            Pattern::Jumptrill => "jumptrill",
+            Pattern::Jack => "jack",
+            Pattern::Chordjack => "chordjack",
+            Pattern::Bracket => "bracket",
+            Pattern::LongNoteRelease => "ln_release",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.name() == name)
+    }
+}
+
+/// Consecutive rows of the same pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub pattern: Pattern,
+    pub offset: i32,
+    /// The offset of the last row.
+    pub end_offset: i32,
+    pub note_count: usize,
+}
+
+/// Notes of each pattern in a range of bars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub offset: i32,
+    pub end_offset: i32,
+    pub note_count: usize,
+    /// Patterns with their note counts, the most first.
+    pub patterns: Vec<(Pattern, usize)>,
+}
+
+impl Section {
+    pub fn main_pattern(&self) -> Option<Pattern> {
+        self.patterns.first().map(|v| v.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternAnalysis {
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+}
+
+impl PatternAnalysis {
+    /// The ratio of notes in `pattern`.
+    pub fn share(&self, pattern: Pattern) -> f32 {
+        let total = self.segments.iter().map(|v| v.note_count).sum::<usize>();
+        let count = self
+            .segments
+            .iter()
+            .filter(|v| v.pattern == pattern)
+            .map(|v| v.note_count)
+            .sum::<usize>();
+        match total {
+            0 => 0.0,
+            total => count as f32 / total as f32,
+        }
+    }
+}
+
+/// Notes hit at the same time.
+struct Row {
+    offset: i32,
+    mask: u64,
+    /// Some long note in the row is released apart from another held one.
+    is_release: bool,
+}
+
+impl Row {
+    fn size(&self) -> usize {
+        self.mask.count_ones() as usize
+    }
+}
+
+fn rows(beatmap: &Beatmap) -> Vec<Row> {
+    let mut objects = beatmap.objects.clone();
+    objects.sort_by_key(|v| (v.offset(), v.column()));
+    let long_notes = objects
+        .iter()
+        .filter_map(|v| match *v {
+            Object::LongNote {
+                offset, end_offset, ..
+            } => Some((offset, end_offset)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let is_release = |offset: i32, end_offset: i32| {
+        long_notes.iter().any(|(other_offset, other_end)| {
+            *other_offset < end_offset
+                && *other_end > offset
+                && (other_end - end_offset).abs() > RELEASE_TOLERANCE
+        })
+    };
+
+    let mut rows = Vec::<Row>::new();
+    for object in objects {
+        let bit = 1u64 << object.column().min(63);
+        let release = object
+            .end_offset()
+            .is_some_and(|end| is_release(object.offset(), end));
+        match rows.last_mut() {
+            Some(row) if row.offset == object.offset() => {
+                row.mask |= bit;
+                row.is_release |= release;
+            }
+            _ => rows.push(Row {
+                offset: object.offset(),
+                mask: bit,
+                is_release: release,
+            }),
+        }
+    }
+    rows
+}
+
+/// Label a row by the rows around it.
+fn label(rows: &[Row], idx: usize) -> Pattern {
+    let begin = idx.saturating_sub(WINDOW.0);
+    let end = (idx + WINDOW.1 + 1).min(rows.len());
+    let window = &rows[begin..end];
+    let count = window.len() as f32;
+    let ratio = |f: &dyn Fn(usize) -> bool| (begin..end).filter(|i| f(*i)).count() as f32 / count;
+
+    if ratio(&|i| rows[i].is_release) >= 0.5 {
+        return Pattern::LongNoteRelease;
+    }
+    let average_size = window.iter().map(|v| v.size()).sum::<usize>() as f32 / count;
+    let is_jack = |i: usize| i > 0 && rows[i].mask & rows[i - 1].mask != 0;
+    if ratio(&is_jack) >= 0.5 {
+        return match average_size >= 1.75 {
+            true => Pattern::Chordjack,
+            false => Pattern::Jack,
+        };
+    }
+    // Alternating with the row two before or two after.
+    let is_alternating = |i: usize| {
+        let alternates =
+            |j: usize, k: usize| rows[i].mask == rows[j].mask && rows[i].mask & rows[k].mask == 0;
+        (i > 1 && alternates(i - 2, i - 1)) || (i + 2 < rows.len() && alternates(i + 2, i + 1))
+    };
+    if ratio(&is_alternating) >= 0.75 {
+        return match average_size >= 1.75 {
+            true => Pattern::Jumptrill,
+            false => Pattern::Trill,
+        };
+    }
+    if ratio(&|i| rows[i].size() >= 3) >= 0.25 {
+        return Pattern::Handstream;
+    }
+    let jump_ratio = ratio(&|i| rows[i].size() == 2);
+    if jump_ratio >= 0.25 {
+        let is_bracket = |i: usize| rows[i].size() == 2 && rows[i].mask & (rows[i].mask >> 1) != 0;
+        return match ratio(&is_bracket) >= jump_ratio * 0.75 {
+            true => Pattern::Bracket,
+            false => Pattern::Jumpstream,
+        };
+    }
+    Pattern::Stream
+}
+
+/// Merge short runs of labels into the longer neighbor.
+fn smooth(labels: &mut [Pattern]) {
+    let mut runs = Vec::<(usize, usize)>::new();
+    for (idx, pattern) in labels.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if labels[run.0] == *pattern => run.1 = idx + 1,
+            _ => runs.push((idx, idx + 1)),
+        }
+    }
+    for (idx, &(begin, end)) in runs.iter().enumerate() {
+        if end - begin >= MIN_RUN {
+            continue;
+        }
+        let previous = idx.checked_sub(1).map(|i| runs[i]);
+        let next = runs.get(idx + 1).copied();
+        let neighbor = match (previous, next) {
+            (Some(p), Some(n)) if n.1 - n.0 > p.1 - p.0 => n,
+            (Some(p), _) => p,
+            (None, Some(n)) => n,
+            (None, None) => continue,
+        };
+        if neighbor.1 - neighbor.0 < MIN_RUN {
+            continue;
+        }
+        let pattern = labels[neighbor.0];
+        labels[begin..end].fill(pattern);
+    }
+}
+
+/// Label the patterns of `beatmap`, and break them down into sections of `section_measures` bars.
+pub fn analyze(beatmap: &Beatmap, section_measures: usize) -> PatternAnalysis {
+    let rows = rows(beatmap);
+    let mut labels = (0..rows.len()).map(|i| label(&rows, i)).collect::<Vec<_>>();
+    smooth(&mut labels);
+
+    let mut segments = Vec::<Segment>::new();
+    for (idx, (row, pattern)) in rows.iter().zip(&labels).enumerate() {
+        let is_continued = idx > 0 && row.offset - rows[idx - 1].offset <= MAX_GAP;
+        match segments.last_mut() {
+            Some(segment) if is_continued && segment.pattern == *pattern => {
+                segment.end_offset = row.offset;
+                segment.note_count += row.size();
+            }
+            _ => segments.push(Segment {
+                pattern: *pattern,
+                offset: row.offset,
+                end_offset: row.offset,
+                note_count: row.size(),
+            }),
+        }
+    }
+
+    let Some(end) = beatmap.end_offset() else {
+        return PatternAnalysis {
+            segments,
+            sections: vec![],
+        };
+    };
+    let mut bounds = beatmap
+        .measure_offsets(end + 1)
+        .into_iter()
+        .step_by(section_measures.max(1))
+        .collect::<Vec<_>>();
+    if bounds.first().is_none_or(|v| *v > rows[0].offset) {
+        bounds.insert(0, rows[0].offset);
+    }
+    bounds.push(end + 1);
+    let sections = bounds
+        .windows(2)
+        .map(|bound| {
+            let mut patterns = Vec::<(Pattern, usize)>::new();
+            let mut note_count = 0;
+            for (row, pattern) in rows.iter().zip(&labels) {
+                if row.offset < bound[0] || row.offset >= bound[1] {
+                    continue;
+                }
+                note_count += row.size();
+                match patterns.iter_mut().find(|v| v.0 == *pattern) {
+                    Some(it) => it.1 += row.size(),
+                    None => patterns.push((*pattern, row.size())),
+                }
+            }
+            patterns.sort_by_key(|v| std::cmp::Reverse(v.1));
+            Section {
+                offset: bound[0],
+                end_offset: bound[1],
+                note_count,
+                patterns,
+            }
+        })
+        .filter(|v| v.note_count > 0)
+        .collect();
+
+    PatternAnalysis { segments, sections }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, TimeSignature};
+
+    #[test]
+    fn analyze_patterns() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        // A bar is 2000 ms.
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        let mut push = |offset: i32, columns: &[u32]| {
+            for column in columns {
+                beatmap.objects.push(Object::Note {
+                    column: *column,
+                    offset,
+                });
+            }
+        };
+        // A bar each of stream, jumptrill and chordjack in 1/4.
+        for i in 0..16 {
+            push(i * 125, &[[0, 2, 1, 3][i as usize % 4]]);
+        }
+        for i in 16..32 {
+            push(i * 125, [&[0, 1], &[2, 3]][i as usize % 2]);
+        }
+        for i in 32..48 {
+            push(i * 125, [&[0, 1, 2], &[1, 2, 3]][i as usize % 2]);
+        }
+
+        let analysis = analyze(&beatmap, 1);
+        let patterns = analysis
+            .segments
+            .iter()
+            .filter(|v| v.note_count >= 8)
+            .map(|v| v.pattern)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            patterns,
+            vec![Pattern::Stream, Pattern::Jumptrill, Pattern::Chordjack]
+        );
+        let main_patterns = analysis
+            .sections
+            .iter()
+            .map(|v| v.main_pattern())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            main_patterns,
+            vec![
+                Some(Pattern::Stream),
+                Some(Pattern::Jumptrill),
+                Some(Pattern::Chordjack)
+            ]
+        );
+        assert!(analysis.share(Pattern::Chordjack) > 0.4);
+    }
+}
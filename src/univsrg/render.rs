@@ -0,0 +1,405 @@
+//! Drawing beatmaps as preview images.
+
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use super::types::Beatmap;
+
+pub type Color = [u8; 3];
+
+const BACKGROUND: Color = [0x10, 0x10, 0x10];
+const PLAYFIELD: Color = [0x28, 0x28, 0x28];
+const MEASURE_LINE: Color = [0x80, 0x80, 0x80];
+const BPM_LINE: Color = [0xe0, 0x40, 0x40];
+const NOTE_WHITE: Color = [0xf0, 0xf0, 0xf0];
+const NOTE_BLUE: Color = [0x40, 0xa0, 0xf0];
+const NOTE_YELLOW: Color = [0xf0, 0xc8, 0x40];
+const NOTE_SCRATCH: Color = [0xf0, 0x50, 0x50];
+const SV_FASTER: Color = [0x50, 0xd0, 0x50];
+const SV_SLOWER: Color = [0xd0, 0x50, 0x50];
+const SV_NORMAL: Color = [0xc0, 0xc0, 0xc0];
+
+/// Space around and between strips in pixels.
+const MARGIN: i32 = 10;
+/// Width of the lane for SV markers at the right of every strip.
+const SV_LANE_WIDTH: i32 = 6;
+/// Most pixels of a canvas, so that broken offsets or sizes can't take all memory.
+const MAX_PIXELS: u64 = 1 << 26;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub color: Color,
+}
+
+/// A picture made of filled rectangles, drawn in order.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub background: Color,
+    pub rects: Vec<Rect>,
+}
+
+impl Canvas {
+    pub fn to_svg(&self) -> String {
+        let hex = |c: &Color| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+            self.width, self.height
+        );
+        writeln!(
+            svg,
+            "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+            hex(&self.background)
+        )
+        .unwrap();
+        for rect in &self.rects {
+            writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                hex(&rect.color)
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// RGB pixels from the top left, row by row.
+    fn rasterize(&self) -> Vec<u8> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let mut pixels = self.background.repeat((self.width * self.height) as usize);
+        for rect in &self.rects {
+            let (left, right) = (rect.x.max(0), (rect.x + rect.width).min(width));
+            let (top, bottom) = (rect.y.max(0), (rect.y + rect.height).min(height));
+            for y in top..bottom {
+                for x in left..right {
+                    let idx = ((y * width + x) * 3) as usize;
+                    pixels[idx..idx + 3].copy_from_slice(&rect.color);
+                }
+            }
+        }
+        pixels
+    }
+
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.rasterize())
+            .map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)?;
+        Ok(bytes)
+    }
+
+    /// Save as SVG or PNG by the extension of `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let bytes = match path.extension().and_then(|v| v.to_str()) {
+            Some("svg") => self.to_svg().into_bytes(),
+            Some("png") => self.to_png()?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unsupported image type of {}.", path.to_string_lossy()),
+                ))
+            }
+        };
+        fs::write(path, bytes)
+    }
+}
+
+/// Sizes of a rendered beatmap.
+pub struct RenderOptions {
+    pub column_width: u32,
+    pub note_height: u32,
+    pub pixels_per_second: f32,
+    /// Height of every strip in pixels.
+    pub strip_height: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            column_width: 16,
+            note_height: 6,
+            pixels_per_second: 300.0,
+            strip_height: 1200,
+        }
+    }
+}
+
+/// Places times of a beatmap on strips.
+struct Strips {
+    start: i32,
+    /// Duration of every strip in ms.
+    duration: f32,
+    count: i32,
+    width: i32,
+    height: i32,
+    pixels_per_ms: f32,
+}
+
+impl Strips {
+    fn index(&self, time: i32) -> i32 {
+        (((time - self.start) as f32 / self.duration).floor() as i32).clamp(0, self.count - 1)
+    }
+    fn left(&self, index: i32) -> i32 {
+        MARGIN + index * (self.width + MARGIN)
+    }
+    fn y(&self, index: i32, time: f32) -> i32 {
+        let strip_start = self.start as f32 + index as f32 * self.duration;
+        MARGIN + self.height - ((time - strip_start) * self.pixels_per_ms).round() as i32
+    }
+
+    /// Rectangles from `start` to `end` at `x` in every strip they cross, at least `min_height` tall.
+    fn rects(
+        &self,
+        start: i32,
+        end: i32,
+        x: i32,
+        width: i32,
+        min_height: i32,
+        color: Color,
+    ) -> Vec<Rect> {
+        (self.index(start)..=self.index(end))
+            .map(|index| {
+                let strip_start = self.start as f32 + index as f32 * self.duration;
+                let bottom = self.y(index, (start as f32).max(strip_start));
+                let top = self.y(index, (end as f32).min(strip_start + self.duration));
+                let height = (bottom - top).max(min_height);
+                Rect {
+                    x: self.left(index) + x,
+                    y: bottom - height,
+                    width,
+                    height,
+                    color,
+                }
+            })
+            .collect()
+    }
+}
+
+fn column_color(beatmap: &Beatmap, column: u32, column_count: u32) -> Color {
+    if beatmap
+        .layout
+        .as_ref()
+        .is_some_and(|v| v.is_scratch(column))
+    {
+        return NOTE_SCRATCH;
+    }
+    if column_count % 2 == 1 && column == column_count / 2 {
+        return NOTE_YELLOW;
+    }
+    // Alternate from the outside in, so both hands look the same.
+    match column.min(column_count.saturating_sub(column + 1)) % 2 {
+        0 => NOTE_WHITE,
+        _ => NOTE_BLUE,
+    }
+}
+
+fn dim(color: Color) -> Color {
+    color.map(|v| v / 2)
+}
+
+/// Draw a beatmap as columns scrolling upwards, split into strips from left to right.
+///
+/// Fails if the picture would be larger than [`MAX_PIXELS`].
+pub fn render(beatmap: &Beatmap, options: &RenderOptions) -> io::Result<Canvas> {
+    // Objects out of the columns still get drawn.
+    let column_count = beatmap
+        .objects
+        .iter()
+        .map(|v| v.column().saturating_add(1))
+        .chain(beatmap.column_count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let start = beatmap
+        .objects
+        .iter()
+        .map(|v| v.offset())
+        .min()
+        .unwrap_or(0)
+        .min(0);
+    let end = beatmap.end_offset().unwrap_or(0).saturating_add(1000);
+    let duration = options.strip_height as f32 / options.pixels_per_second * 1000.0;
+    let count = ((end as i64 - start as i64) as f32 / duration)
+        .ceil()
+        .max(1.0) as u64;
+    let strip_width = column_count as u64 * options.column_width as u64 + SV_LANE_WIDTH as u64;
+    let width = count * (strip_width + MARGIN as u64) + MARGIN as u64;
+    let height = options.strip_height as u64 + 2 * MARGIN as u64;
+    if width.saturating_mul(height) > MAX_PIXELS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The picture of {} strips is too large ({} x {} pixels).",
+                count, width, height
+            ),
+        ));
+    }
+    // Every size fits in i32 from here.
+    let column_width = options.column_width as i32;
+    let strips = Strips {
+        start,
+        duration,
+        count: count as i32,
+        width: strip_width as i32,
+        height: options.strip_height as i32,
+        pixels_per_ms: options.pixels_per_second / 1000.0,
+    };
+    let playfield_width = column_count as i32 * column_width;
+
+    let mut rects = vec![];
+    for index in 0..strips.count {
+        rects.push(Rect {
+            x: strips.left(index),
+            y: MARGIN,
+            width: playfield_width,
+            height: strips.height,
+            color: PLAYFIELD,
+        });
+    }
+    for offset in beatmap.measure_offsets(end) {
+        rects.extend(strips.rects(offset, offset, 0, playfield_width, 1, MEASURE_LINE));
+    }
+    for btp in &beatmap.bpm_time_points {
+        rects.extend(strips.rects(btp.offset, btp.offset, 0, playfield_width, 2, BPM_LINE));
+    }
+    let mut velocity = 1.0;
+    for etp in &beatmap.effect_time_points {
+        if (etp.velocity_multiplier - velocity).abs() > 1e-3 {
+            let color = match etp.velocity_multiplier {
+                v if (v - 1.0).abs() <= 1e-3 => SV_NORMAL,
+                v if v > 1.0 => SV_FASTER,
+                _ => SV_SLOWER,
+            };
+            rects.extend(strips.rects(
+                etp.offset,
+                etp.offset,
+                playfield_width,
+                SV_LANE_WIDTH,
+                3,
+                color,
+            ));
+        }
+        velocity = etp.velocity_multiplier;
+    }
+
+    let note_height = options.note_height as i32;
+    let mut objects = beatmap.objects.clone();
+    objects.sort_by_key(|v| v.offset());
+    for object in &objects {
+        let color = column_color(beatmap, object.column(), column_count);
+        let x = object.column() as i32 * column_width + 1;
+        if let Some(end_offset) = object.end_offset() {
+            rects.extend(strips.rects(
+                object.offset(),
+                end_offset,
+                x + column_width / 4,
+                column_width / 2 - 1,
+                1,
+                dim(color),
+            ));
+        }
+        rects.extend(strips.rects(
+            object.offset(),
+            object.offset(),
+            x,
+            column_width - 2,
+            note_height,
+            color,
+        ));
+    }
+
+    Ok(Canvas {
+        width: width as u32,
+        height: height as u32,
+        background: BACKGROUND,
+        rects,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, Object, TimeSignature};
+
+    #[test]
+    fn render_strips() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 500,
+            },
+            // Crosses from the first strip to the second.
+            Object::LongNote {
+                column: 1,
+                offset: 3000,
+                end_offset: 5000,
+            },
+        ];
+        let options = RenderOptions {
+            column_width: 10,
+            note_height: 4,
+            pixels_per_second: 100.0,
+            strip_height: 400,
+        };
+        let canvas = render(&beatmap, &options).unwrap();
+        // 6 s in strips of 4 s.
+        assert_eq!(
+            canvas.width,
+            (2 * (40 + SV_LANE_WIDTH + MARGIN) + MARGIN) as u32
+        );
+        assert_eq!(canvas.height, 420);
+        assert!(canvas.to_svg().contains("<rect x=\"11\" y=\"356\""));
+
+        let pixels = canvas.rasterize();
+        let pixel = |x: u32, y: u32| {
+            let idx = ((y * canvas.width + x) * 3) as usize;
+            [pixels[idx], pixels[idx + 1], pixels[idx + 2]]
+        };
+        // The note at 0.5 s is 50 px above the bottom of the first strip.
+        assert_eq!(pixel(15, 358), NOTE_WHITE);
+        // The long note continues at the bottom of the second strip.
+        let second = (MARGIN + 40 + SV_LANE_WIDTH + MARGIN) as u32;
+        assert_eq!(pixel(second + 15, 405), dim(NOTE_BLUE));
+        assert!(canvas.to_png().unwrap().starts_with(b"\x89PNG"));
+
+        // A column count smaller than the objects need widens the playfield.
+        beatmap.column_count = Some(0);
+        let canvas = render(&beatmap, &options).unwrap();
+        assert_eq!(
+            canvas.width,
+            (2 * (20 + SV_LANE_WIDTH + MARGIN) + MARGIN) as u32
+        );
+
+        // Offsets far apart would need too many strips.
+        beatmap.objects.push(Object::Note {
+            column: u32::MAX,
+            offset: i32::MAX,
+        });
+        beatmap.objects.push(Object::Note {
+            column: 0,
+            offset: i32::MIN,
+        });
+        assert!(render(&beatmap, &options).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::File,
+    fs::{create_dir_all, File},
     io::{self, Read, Write},
     ops::Deref,
     path::{Path, PathBuf},
@@ -16,13 +16,11 @@ pub struct ResourceEntity {
 pub struct ResourceEntry(Rc<ResourceEntity>);
 
 impl ResourceEntry {
-    fn new(original_path: PathBuf, bytes: Vec<u8>) -> Self {
-        Self {
-            0: Rc::from(ResourceEntity {
-                original_path,
-                bytes,
-            }),
-        }
+    pub fn new(original_path: PathBuf, bytes: Vec<u8>) -> Self {
+        Self(Rc::from(ResourceEntity {
+            original_path,
+            bytes,
+        }))
     }
 
     pub fn new_from_file_in_bundle(bundle_base: &Path, original_path: PathBuf) -> io::Result<Self> {
@@ -41,11 +39,13 @@ impl Deref for ResourceEntry {
     }
 }
 
+#[derive(Default)]
 pub struct ResourcePool {
     entries: HashSet<ResourceEntry>,
     path_to_entry: HashMap<PathBuf, ResourceEntry>,
 }
 
+#[derive(Default)]
 pub struct ResourceOut {
     entry_to_path: HashMap<ResourceEntry, PathBuf>,
 }
@@ -66,6 +66,18 @@ impl ResourcePool {
             .insert(entry.original_path.clone(), entry.clone());
         true
     }
+    pub fn remove(&mut self, entry: &ResourceEntry) -> bool {
+        if !self.entries.remove(entry) {
+            return false;
+        }
+        if self.path_to_entry.get(&entry.original_path) == Some(entry) {
+            self.path_to_entry.remove(&entry.original_path);
+        }
+        true
+    }
+    pub fn contains(&self, entry: &ResourceEntry) -> bool {
+        self.entries.contains(entry)
+    }
     pub fn get_entry_from_path(&self, path: &Path) -> Option<ResourceEntry> {
         self.path_to_entry.get(path).cloned()
     }
@@ -107,10 +119,13 @@ impl ResourceOut {
             }
 
             // Save the path.
-            self.entry_to_path
-                .insert(entry.clone(), original_path.clone());
+            self.entry_to_path.insert(
+                entry.clone(),
+                inflated_path.strip_prefix(&dir).unwrap().to_owned(),
+            );
 
             // Inflate the path.
+            create_dir_all(inflated_path.parent().unwrap())?;
 
             // Note: 文件写操作。
             let mut output = File::create(inflated_path).unwrap(); // Assume the creation is always valid.
@@ -129,6 +144,8 @@ impl ResourceOut {
 }
 
 #[cfg(test)]
+// The baseline tests compare booleans with `assert_eq!`.
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     // Note: 测试模块常用 use super::* 引入要测试的所有内容。
     use super::*;
@@ -0,0 +1,499 @@
+//! Playing beatmaps without a game, with autoplay or recorded key events.
+//!
+//! Judgements and health approximate osu!mania, where heads and tails of long notes are judged
+//! apart. Health only changes by judgements, without the drain over time of the game.
+
+use std::{collections::VecDeque, io};
+
+use serde::Serialize;
+
+use super::{
+    judgement::HitWindows,
+    types::{Beatmap, Object},
+};
+
+/// Windows of tails are wider, as releasing is harder to time. It is the release lenience of
+/// osu!lazer, though judging by the scaled windows is an approximation of it.
+const TAIL_WINDOW_SCALE: f32 = 1.5;
+/// Defaults of osu! when a beatmap has no difficulty.
+const DEFAULT_OD: f32 = 5.0;
+const DEFAULT_HP: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Judgement {
+    Perfect,
+    Great,
+    Good,
+    Ok,
+    Meh,
+    Miss,
+}
+
+impl Judgement {
+    /// Score of osu!mania.
+    fn value(&self) -> u32 {
+        match self {
+            Judgement::Perfect => 320,
+            Judgement::Great => 300,
+            Judgement::Good => 200,
+            Judgement::Ok => 100,
+            Judgement::Meh => 50,
+            Judgement::Miss => 0,
+        }
+    }
+    /// Weight in accuracy of osu!mania, where MAX is the same as 300.
+    fn accuracy_value(&self) -> u32 {
+        self.value().min(300)
+    }
+    /// The judgement of an absolute error, or `None` if it is too early to hit.
+    fn from_error(error: f32, windows: &HitWindows, scale: f32) -> Option<Self> {
+        let error = error.abs();
+        [
+            (windows.perfect, Judgement::Perfect),
+            (windows.great, Judgement::Great),
+            (windows.good, Judgement::Good),
+            (windows.ok, Judgement::Ok),
+            (windows.meh, Judgement::Meh),
+            (windows.miss, Judgement::Miss),
+        ]
+        .into_iter()
+        .find(|(window, _)| error <= window * scale)
+        .map(|(_, judgement)| judgement)
+    }
+}
+
+/// A key pressed or released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub offset: i32,
+    pub column: u32,
+    pub pressed: bool,
+}
+
+/// Key events pressing every note on time, and holding long notes until their ends.
+pub fn autoplay(beatmap: &Beatmap) -> Vec<KeyEvent> {
+    let mut objects = beatmap.objects.clone();
+    objects.sort_by_key(|v| (v.column(), v.offset()));
+    let mut events = vec![];
+    for (idx, object) in objects.iter().enumerate() {
+        let next = objects
+            .get(idx + 1)
+            .filter(|v| v.column() == object.column())
+            .map_or(i32::MAX, |v| v.offset());
+        // Release notes a little later, but before the next one.
+        let hold = ((next as i64 - object.offset() as i64) / 2).min(40) as i32;
+        let release = object
+            .end_offset()
+            .unwrap_or(object.offset().saturating_add(hold));
+        events.push(KeyEvent {
+            offset: object.offset(),
+            column: object.column(),
+            pressed: true,
+        });
+        events.push(KeyEvent {
+            offset: release,
+            column: object.column(),
+            pressed: false,
+        });
+    }
+    events.sort_by_key(|v| (v.offset, v.pressed));
+    events
+}
+
+/// Parse key events from lines of `<offset> <column> <press|release>`. `#` starts a comment.
+pub fn parse_key_events(text: &str) -> io::Result<Vec<KeyEvent>> {
+    let mut events = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid key event at line {}: {}", idx + 1, line),
+            )
+        };
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [offset, column, action] = fields[..] else {
+            return Err(invalid());
+        };
+        events.push(KeyEvent {
+            offset: offset.parse().map_err(|_| invalid())?,
+            column: column.parse().map_err(|_| invalid())?,
+            pressed: match action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(invalid()),
+            },
+        });
+    }
+    events.sort_by_key(|v| (v.offset, v.pressed));
+    Ok(events)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct JudgementCounts {
+    pub perfect: u32,
+    pub great: u32,
+    pub good: u32,
+    pub ok: u32,
+    pub meh: u32,
+    pub miss: u32,
+}
+
+impl JudgementCounts {
+    pub fn get(&self, judgement: Judgement) -> u32 {
+        match judgement {
+            Judgement::Perfect => self.perfect,
+            Judgement::Great => self.great,
+            Judgement::Good => self.good,
+            Judgement::Ok => self.ok,
+            Judgement::Meh => self.meh,
+            Judgement::Miss => self.miss,
+        }
+    }
+    fn add(&mut self, judgement: Judgement) {
+        *match judgement {
+            Judgement::Perfect => &mut self.perfect,
+            Judgement::Great => &mut self.great,
+            Judgement::Good => &mut self.good,
+            Judgement::Ok => &mut self.ok,
+            Judgement::Meh => &mut self.meh,
+            Judgement::Miss => &mut self.miss,
+        } += 1;
+    }
+    pub fn total(&self) -> u32 {
+        self.perfect + self.great + self.good + self.ok + self.meh + self.miss
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationResult {
+    pub counts: JudgementCounts,
+    /// Up to 1,000,000, by the score of every judgement.
+    pub score: u32,
+    /// From 0 to 1.
+    pub accuracy: f32,
+    pub max_combo: u32,
+    /// Health at the end, from 0 to 1.
+    pub hp: f32,
+    /// The time health ran out, if it did.
+    pub failed_at: Option<i32>,
+}
+
+/// A judgement of a note, or of a head or a tail of a long note.
+struct Hit {
+    offset: i32,
+    is_long_note: bool,
+    judgement: Judgement,
+}
+
+/// A column waiting for its objects to be hit.
+#[derive(Default)]
+struct Lane {
+    objects: VecDeque<Object>,
+    /// The end of the long note held.
+    holding: Option<i32>,
+}
+
+pub struct Simulator<'a> {
+    beatmap: &'a Beatmap,
+    pub hit_windows: HitWindows,
+    /// HP drain rate of osu!.
+    pub hp_difficulty: f32,
+}
+
+impl<'a> Simulator<'a> {
+    /// Use the hit windows and HP drain rate of a beatmap.
+    pub fn new(beatmap: &'a Beatmap) -> Self {
+        Self {
+            beatmap,
+            hit_windows: beatmap.hit_windows.unwrap_or_else(|| {
+                HitWindows::from_osu_od(beatmap.acc_difficulty.unwrap_or(DEFAULT_OD))
+            }),
+            hp_difficulty: beatmap.hp_difficulty.unwrap_or(DEFAULT_HP),
+        }
+    }
+
+    /// Miss notes too late to hit before `offset`, and complete long notes held long enough.
+    fn expire(&self, lanes: &mut [Lane], offset: i32, hits: &mut Vec<Hit>) {
+        let tail_meh = self.hit_windows.meh * TAIL_WINDOW_SCALE;
+        for lane in lanes {
+            if let Some(end) = lane.holding {
+                if (offset - end) as f32 > tail_meh {
+                    hits.push(Hit {
+                        offset: end,
+                        is_long_note: true,
+                        judgement: Judgement::Perfect,
+                    });
+                    lane.holding = None;
+                }
+            }
+            while let Some(object) = lane.objects.front() {
+                let late = object.offset() as f32 + self.hit_windows.meh;
+                if late >= offset as f32 || lane.holding.is_some() {
+                    break;
+                }
+                let missed_at = late.ceil() as i32;
+                let is_long_note = object.end_offset().is_some();
+                hits.push(Hit {
+                    offset: missed_at,
+                    is_long_note,
+                    judgement: Judgement::Miss,
+                });
+                if is_long_note {
+                    hits.push(Hit {
+                        offset: missed_at,
+                        is_long_note,
+                        judgement: Judgement::Miss,
+                    });
+                }
+                lane.objects.pop_front();
+            }
+        }
+    }
+
+    fn handle(&self, lane: &mut Lane, event: &KeyEvent, hits: &mut Vec<Hit>) {
+        if !event.pressed {
+            if let Some(end) = lane.holding.take() {
+                // Too early releases are misses.
+                let judgement = Judgement::from_error(
+                    (event.offset - end) as f32,
+                    &self.hit_windows,
+                    TAIL_WINDOW_SCALE,
+                )
+                .unwrap_or(Judgement::Miss);
+                hits.push(Hit {
+                    offset: event.offset,
+                    is_long_note: true,
+                    judgement,
+                });
+            }
+            return;
+        }
+        let Some(object) = lane.objects.front().copied() else {
+            return;
+        };
+        if lane.holding.is_some() {
+            return;
+        }
+        let Some(judgement) = Judgement::from_error(
+            (event.offset - object.offset()) as f32,
+            &self.hit_windows,
+            1.0,
+        ) else {
+            return;
+        };
+        lane.objects.pop_front();
+        let is_long_note = object.end_offset().is_some();
+        hits.push(Hit {
+            offset: event.offset,
+            is_long_note,
+            judgement,
+        });
+        if let Some(end) = object.end_offset() {
+            if judgement == Judgement::Miss {
+                hits.push(Hit {
+                    offset: event.offset,
+                    is_long_note,
+                    judgement,
+                });
+            } else {
+                lane.holding = Some(end);
+            }
+        }
+    }
+
+    /// Change of health by a judgement, after the constants of osu!mania. They are not checked
+    /// against the game, so health is an approximation.
+    fn hp_change(&self, hit: &Hit) -> f32 {
+        let drain = self.hp_difficulty;
+        let change = match hit.judgement {
+            Judgement::Miss => -(drain + 1.0) * 0.0075,
+            Judgement::Meh => -(drain + 1.0) * 0.0016,
+            Judgement::Ok => 0.0,
+            Judgement::Good => 0.004 - drain * 0.0004,
+            Judgement::Great => 0.005 - drain * 0.0005,
+            Judgement::Perfect => 0.0055 - drain * 0.0005,
+        };
+        // Heads and tails share the change of a note.
+        match hit.is_long_note {
+            true => change / 2.0,
+            false => change,
+        }
+    }
+
+    pub fn run(&self, events: &[KeyEvent]) -> SimulationResult {
+        let column_count = self
+            .beatmap
+            .objects
+            .iter()
+            .map(|v| v.column() as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut lanes = (0..column_count)
+            .map(|_| Lane::default())
+            .collect::<Vec<_>>();
+        let mut objects = self.beatmap.objects.clone();
+        objects.sort_by_key(|v| v.offset());
+        for object in objects {
+            lanes[object.column() as usize].objects.push_back(object);
+        }
+
+        let mut hits = vec![];
+        for event in events {
+            self.expire(&mut lanes, event.offset, &mut hits);
+            if let Some(lane) = lanes.get_mut(event.column as usize) {
+                self.handle(lane, event, &mut hits);
+            }
+        }
+        self.expire(&mut lanes, i32::MAX, &mut hits);
+        hits.sort_by_key(|v| v.offset);
+
+        let mut counts = JudgementCounts::default();
+        let (mut combo, mut max_combo) = (0, 0);
+        let mut hp = 1.0_f32;
+        let mut failed_at = None;
+        let (mut value, mut accuracy_value) = (0_u64, 0_u64);
+        for hit in &hits {
+            counts.add(hit.judgement);
+            value += hit.judgement.value() as u64;
+            accuracy_value += hit.judgement.accuracy_value() as u64;
+            combo = match hit.judgement {
+                Judgement::Miss => 0,
+                _ => combo + 1,
+            };
+            max_combo = max_combo.max(combo);
+            hp = (hp + self.hp_change(hit)).clamp(0.0, 1.0);
+            if hp <= 0.0 && failed_at.is_none() {
+                failed_at = Some(hit.offset);
+            }
+        }
+        let total = (hits.len() as u64).max(1);
+        SimulationResult {
+            counts,
+            score: (value * 1_000_000 / (320 * total)) as u32,
+            accuracy: accuracy_value as f32 / (300 * total) as f32,
+            max_combo,
+            hp,
+            failed_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_example_beatmap() -> Beatmap {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.acc_difficulty = Some(8.0);
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 1000,
+            },
+            Object::Note {
+                column: 0,
+                offset: 1050,
+            },
+            Object::LongNote {
+                column: 1,
+                offset: 1000,
+                end_offset: 2000,
+            },
+            Object::Note {
+                column: 3,
+                offset: 1500,
+            },
+        ];
+        beatmap
+    }
+
+    #[test]
+    fn simulate_autoplay() {
+        let beatmap = new_example_beatmap();
+        let result = Simulator::new(&beatmap).run(&autoplay(&beatmap));
+        assert_eq!(result.counts.perfect, 5);
+        assert_eq!(result.counts.total(), 5);
+        assert_eq!(result.score, 1_000_000);
+        assert_eq!(result.accuracy, 1.0);
+        assert_eq!(result.max_combo, 5);
+        assert_eq!(result.failed_at, None);
+    }
+
+    #[test]
+    fn simulate_key_events() {
+        let beatmap = new_example_beatmap();
+        // 300 at OD 8 is 40 ms. The second note is missed, and the long note is released early.
+        let events = parse_key_events(
+            "# offset column action\n\
+            1030 0 press\n\
+            1040 0 release\n\
+            1000 1 press\n\
+            1500 1 release\n\
+            1500 3 press\n\
+            1520 3 release\n",
+        )
+        .unwrap();
+        let simulator = Simulator::new(&beatmap);
+        assert_eq!(simulator.hit_windows.great, 40.0);
+        let result = simulator.run(&events);
+        assert_eq!(
+            result.counts,
+            JudgementCounts {
+                perfect: 2,
+                great: 1,
+                miss: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(result.max_combo, 2);
+        assert!(result.accuracy < 0.7);
+        assert!(parse_key_events("1000 0 hold").is_err());
+
+        // Every miss drains a lot at HP 10.
+        let mut beatmap = beatmap;
+        beatmap.objects = (0..20)
+            .map(|idx| Object::Note {
+                column: 0,
+                offset: idx * 100,
+            })
+            .collect();
+        let mut simulator = Simulator::new(&beatmap);
+        simulator.hp_difficulty = 10.0;
+        let result = simulator.run(&[]);
+        assert_eq!(result.counts.miss, 20);
+        assert!(result.failed_at.is_some());
+    }
+
+    #[test]
+    fn autoplay_negative_offset() {
+        let mut beatmap = Beatmap::new();
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: -500,
+            },
+            Object::Note {
+                column: 1,
+                offset: i32::MAX,
+            },
+        ];
+        let events = autoplay(&beatmap);
+        assert!(events.contains(&KeyEvent {
+            offset: -460,
+            column: 0,
+            pressed: false,
+        }));
+        assert!(events.contains(&KeyEvent {
+            offset: i32::MAX,
+            column: 1,
+            pressed: false,
+        }));
+        let result = Simulator::new(&beatmap).run(&events);
+        assert_eq!(result.counts.perfect, 2);
+    }
+}
@@ -1,13 +1,42 @@
 use std::{io, path::Path};
 
-use super::types::Package;
+use super::types::{Beatmap, Package};
 
 pub trait AppendToUnivsrg {
     fn append_to_univsrg(&self, package: &mut Package) -> io::Result<()>;
 }
 
 pub trait ToOsu {
-    fn to_osu(&self, path: &Path) -> io::Result<()>;
+    /// Returns warnings about what osu! cannot represent exactly.
+    fn to_osu(&self, path: &Path) -> io::Result<Vec<String>>;
 }
 
 pub trait ToMalody {}
+
+/// A step of editing a package in place, run between import and compile.
+pub trait Transform {
+    /// Returns warnings about what the step could not do exactly, e.g. notes dropped.
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>>;
+}
+
+/// A step of editing every beatmap in place in the same way.
+pub trait BeatmapTransform {
+    /// Returns warnings about `beatmap`.
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>>;
+}
+
+impl<T: BeatmapTransform> Transform for T {
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        let mut warnings = vec![];
+        for beatmap in &mut package.beatmaps {
+            let beatmap_warnings = self.transform_beatmap(beatmap)?;
+            let basename = beatmap.make_basename();
+            warnings.extend(
+                beatmap_warnings
+                    .into_iter()
+                    .map(|v| format!("{}: {}", basename, v)),
+            );
+        }
+        Ok(warnings)
+    }
+}
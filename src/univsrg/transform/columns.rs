@@ -0,0 +1,298 @@
+use std::io;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::super::{
+    traits::BeatmapTransform,
+    types::{Beatmap, Layout, Object},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Permutation {
+    Mirror,
+    /// Column `i` is moved to column `columns[i]`.
+    Fixed(Vec<u32>),
+    /// Shuffle the columns.
+    Random {
+        seed: u64,
+    },
+    /// Move every note to a random column, avoiding jacks shorter than `min_jack_interval`
+    /// and notes inside long notes.
+    SRandom {
+        seed: u64,
+        min_jack_interval: i32,
+    },
+}
+
+/// Permute the columns of a beatmap.
+pub struct PermuteColumns {
+    pub permutation: Permutation,
+    /// Keep scratch columns in place. Only the other columns are permuted among themselves.
+    /// [Permutation::Fixed] already tells where every column goes, so it ignores this.
+    pub keep_scratch: bool,
+}
+
+impl PermuteColumns {
+    pub fn new(permutation: Permutation) -> Self {
+        Self {
+            permutation,
+            keep_scratch: false,
+        }
+    }
+
+    /// The columns to permute among.
+    fn movable_columns(&self, beatmap: &Beatmap, column_count: u32) -> Vec<u32> {
+        (0..column_count)
+            .filter(|v| {
+                !self.keep_scratch || !beatmap.layout.as_ref().is_some_and(|l| l.is_scratch(*v))
+            })
+            .collect()
+    }
+
+    /// Make a permutation over all columns from a permutation of the movable ones.
+    fn extend(movable: &[u32], moved_to: &[u32], column_count: u32) -> Vec<u32> {
+        let mut permutation = (0..column_count).collect::<Vec<_>>();
+        for (from, to) in movable.iter().zip(moved_to.iter()) {
+            permutation[*from as usize] = *to;
+        }
+        permutation
+    }
+
+    pub fn apply(&self, beatmap: &mut Beatmap) -> io::Result<()> {
+        let column_count = beatmap.checked_column_count()?;
+        let movable = self.movable_columns(beatmap, column_count);
+        let permutation = match &self.permutation {
+            Permutation::Mirror => {
+                let mirrored = movable.iter().rev().copied().collect::<Vec<_>>();
+                Self::extend(&movable, &mirrored, column_count)
+            }
+            Permutation::Fixed(columns) => {
+                let mut sorted = columns.clone();
+                sorted.sort();
+                if sorted != (0..column_count).collect::<Vec<_>>() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{:?} is not a permutation of {} columns.",
+                            columns, column_count
+                        ),
+                    ));
+                }
+                columns.clone()
+            }
+            Permutation::Random { seed } => {
+                let mut shuffled = movable.clone();
+                shuffled.shuffle(&mut StdRng::seed_from_u64(*seed));
+                Self::extend(&movable, &shuffled, column_count)
+            }
+            Permutation::SRandom {
+                seed,
+                min_jack_interval,
+            } => {
+                s_random(beatmap, &movable, *seed, *min_jack_interval);
+                return Ok(());
+            }
+        };
+
+        for object in &mut beatmap.objects {
+            let column = permutation[object.column() as usize];
+            match object {
+                Object::Note { column: c, .. } | Object::LongNote { column: c, .. } => *c = column,
+            }
+        }
+        // Scratches and pedals move with their columns, while sides stay in place.
+        if let Some(layout) = &beatmap.layout {
+            let mut columns = layout.columns.clone();
+            for (from, to) in permutation.iter().enumerate() {
+                if let (Some(column), Some(original)) =
+                    (columns.get_mut(*to as usize), layout.columns.get(from))
+                {
+                    column.role = original.role;
+                }
+            }
+            beatmap.layout = Some(Layout::from_columns(columns));
+        }
+        Ok(())
+    }
+}
+
+fn s_random(beatmap: &mut Beatmap, movable: &[u32], seed: u64, min_jack_interval: i32) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let column_count = beatmap.column_count.unwrap_or(0) as usize;
+    // The last time each column is busy, i.e. the end of the last note.
+    let mut busy_until = vec![i32::MIN; column_count];
+
+    beatmap.objects.sort_by_key(|v| (v.offset(), v.column()));
+    let mut idx = 0;
+    while idx < beatmap.objects.len() {
+        let offset = beatmap.objects[idx].offset();
+        let chord_end = beatmap.objects[idx..]
+            .iter()
+            .position(|v| v.offset() != offset)
+            .map_or(beatmap.objects.len(), |v| idx + v);
+        let mut used = Vec::<u32>::new();
+        for object in &mut beatmap.objects[idx..chord_end] {
+            if !movable.contains(&object.column()) {
+                used.push(object.column());
+                busy_until[object.column() as usize] = object.end_offset().unwrap_or(offset);
+            }
+        }
+        for object in &mut beatmap.objects[idx..chord_end] {
+            if !movable.contains(&object.column()) {
+                continue;
+            }
+            let free = |c: &&u32| !used.contains(*c) && busy_until[**c as usize] < offset;
+            let comfortable = movable
+                .iter()
+                .filter(free)
+                .filter(|c| busy_until[**c as usize].saturating_add(min_jack_interval) <= offset)
+                .copied()
+                .collect::<Vec<_>>();
+            let candidates = if comfortable.is_empty() {
+                movable.iter().filter(free).copied().collect::<Vec<_>>()
+            } else {
+                comfortable
+            };
+            // Keep the column if every column is taken.
+            let column = if candidates.is_empty() {
+                object.column()
+            } else {
+                candidates[rng.random_range(0..candidates.len())]
+            };
+            match object {
+                Object::Note { column: c, .. } | Object::LongNote { column: c, .. } => *c = column,
+            }
+            used.push(column);
+            busy_until[column as usize] = object.end_offset().unwrap_or(offset);
+        }
+        idx = chord_end;
+    }
+}
+
+impl BeatmapTransform for PermuteColumns {
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>> {
+        self.apply(beatmap)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::LayoutPreset;
+
+    fn new_example_beatmap() -> Beatmap {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(8);
+        beatmap.layout = Some(Layout::new(LayoutPreset::ScratchLeft, 8));
+        for (idx, column) in [0, 1, 2, 3, 4, 5, 6, 7, 1, 1, 1, 1].iter().enumerate() {
+            beatmap.objects.push(Object::Note {
+                column: *column,
+                offset: idx as i32 * 50,
+            });
+        }
+        beatmap.objects.push(Object::LongNote {
+            column: 2,
+            offset: 1000,
+            end_offset: 2000,
+        });
+        beatmap.objects.push(Object::Note {
+            column: 3,
+            offset: 1500,
+        });
+        beatmap
+    }
+
+    #[test]
+    fn permute_columns_mirror() {
+        let mut beatmap = new_example_beatmap();
+        PermuteColumns::new(Permutation::Mirror)
+            .apply(&mut beatmap)
+            .unwrap();
+        assert_eq!(beatmap.objects[0].column(), 7);
+        assert_eq!(beatmap.layout.unwrap().preset, LayoutPreset::ScratchRight);
+
+        let mut beatmap = new_example_beatmap();
+        let mut transform = PermuteColumns::new(Permutation::Mirror);
+        transform.keep_scratch = true;
+        transform.apply(&mut beatmap).unwrap();
+        assert_eq!(beatmap.objects[0].column(), 0);
+        assert_eq!(beatmap.objects[1].column(), 7);
+        assert_eq!(beatmap.layout.unwrap().preset, LayoutPreset::ScratchLeft);
+    }
+
+    #[test]
+    fn permute_columns_fixed() {
+        let mut beatmap = new_example_beatmap();
+        assert!(
+            PermuteColumns::new(Permutation::Fixed(vec![0, 0, 1, 2, 3, 4, 5, 6]))
+                .apply(&mut beatmap)
+                .is_err()
+        );
+        PermuteColumns::new(Permutation::Fixed(vec![1, 0, 2, 3, 4, 5, 6, 7]))
+            .apply(&mut beatmap)
+            .unwrap();
+        assert_eq!(beatmap.objects[0].column(), 1);
+        assert_eq!(beatmap.objects[1].column(), 0);
+    }
+
+    #[test]
+    fn permute_columns_out_of_range() {
+        let mut beatmap = new_example_beatmap();
+        beatmap.objects.push(Object::Note {
+            column: 8,
+            offset: 3000,
+        });
+        for permutation in [
+            Permutation::Mirror,
+            Permutation::SRandom {
+                seed: 0,
+                min_jack_interval: 0,
+            },
+        ] {
+            let error = PermuteColumns::new(permutation)
+                .apply(&mut beatmap)
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn permute_columns_random_is_seeded() {
+        let mut a = new_example_beatmap();
+        let mut b = new_example_beatmap();
+        let mut transform = PermuteColumns::new(Permutation::Random { seed: 114514 });
+        transform.keep_scratch = true;
+        transform.apply(&mut a).unwrap();
+        transform.apply(&mut b).unwrap();
+        assert_eq!(a.objects, b.objects);
+        assert_eq!(a.objects[0].column(), 0);
+    }
+
+    #[test]
+    fn permute_columns_s_random() {
+        let mut beatmap = new_example_beatmap();
+        let mut transform = PermuteColumns::new(Permutation::SRandom {
+            seed: 1919810,
+            min_jack_interval: 120,
+        });
+        transform.keep_scratch = true;
+        transform.apply(&mut beatmap).unwrap();
+        // The jack on column 1 is broken up.
+        let columns = beatmap.objects[8..12]
+            .iter()
+            .map(|v| v.column())
+            .collect::<Vec<_>>();
+        assert!(columns.windows(2).all(|v| v[0] != v[1]));
+        // No note is put inside the long note.
+        let ln = beatmap
+            .objects
+            .iter()
+            .find(|v| v.end_offset().is_some())
+            .unwrap();
+        let note = beatmap.objects.iter().find(|v| v.offset() == 1500).unwrap();
+        assert_ne!(ln.column(), note.column());
+        // Scratch stays.
+        assert_eq!(beatmap.objects[0].column(), 0);
+    }
+}
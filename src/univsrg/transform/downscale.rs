@@ -0,0 +1,265 @@
+use std::io;
+
+use super::{
+    super::{
+        difficulty::osu_mania::{star_rating, ManiaMods},
+        traits::Transform,
+        types::{Beatmap, Object, Package},
+    },
+    long_notes::{LongNoteMode, LongNotes},
+    snap::nearest_snap,
+};
+
+/// Offbeat divisors from the strongest to the weakest.
+const OFFBEAT_DIVISORS: [u32; 7] = [2, 3, 4, 6, 8, 12, 16];
+/// Maximum distance in ms from a snap to be on it.
+const SNAP_TOLERANCE: f64 = 3.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// Objects per second over the duration of the objects.
+    Density(f32),
+    /// Star rating of osu!mania without mods.
+    StarRating(f32),
+}
+
+impl Target {
+    fn measure(&self, beatmap: &Beatmap) -> f32 {
+        match self {
+            Target::Density(_) => density(beatmap),
+            Target::StarRating(_) => star_rating(beatmap, &ManiaMods::default()) as f32,
+        }
+    }
+    fn value(&self) -> f32 {
+        match self {
+            Target::Density(v) | Target::StarRating(v) => *v,
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Density(v) => write!(f, "{} NPS", v),
+            Target::StarRating(v) => write!(f, "{} stars", v),
+        }
+    }
+}
+
+fn density(beatmap: &Beatmap) -> f32 {
+    let first = beatmap.objects.iter().map(|v| v.offset()).min();
+    let (Some(first), Some(last)) = (first, beatmap.end_offset()) else {
+        return 0.0;
+    };
+    beatmap.objects.len() as f32 * 1000.0 / (last - first).max(1000) as f32
+}
+
+/// Make an easier difficulty by thinning the objects down to a target.
+///
+/// Objects on weak beats are removed first, so downbeats, then beats, then halves and so on are kept.
+/// Every extra note of a chord counts as two levels weaker.
+/// Long notes are shortened to `max_long_note_beats`, and kept half a beat away from the next note.
+pub struct Downscale {
+    pub target: Target,
+    /// The version of the new difficulty. The target is appended to the original one if `None`.
+    pub version: Option<String>,
+    pub max_long_note_beats: Option<f32>,
+    /// Turn every long note into a note.
+    pub remove_long_notes: bool,
+}
+
+impl Downscale {
+    pub fn new(target: Target) -> Self {
+        Self {
+            target,
+            version: None,
+            max_long_note_beats: Some(2.0),
+            remove_long_notes: false,
+        }
+    }
+
+    /// 0 for downbeats, 1 for beats, and larger for weaker offbeats.
+    fn strength_level(beatmap: &Beatmap, offset: i32) -> usize {
+        let Some(btp) = beatmap.bpm_time_point_at(offset) else {
+            return 0;
+        };
+        let beats = (offset - btp.offset) as f64 / btp.beat_length() as f64;
+        let beat_error = (beats - beats.round()).abs() * btp.beat_length() as f64;
+        if beat_error <= SNAP_TOLERANCE {
+            let meter = btp.time_signature.closest_meter() as i64;
+            return if (beats.round() as i64).rem_euclid(meter) == 0 {
+                0
+            } else {
+                1
+            };
+        }
+        OFFBEAT_DIVISORS
+            .iter()
+            .position(|divisor| {
+                nearest_snap(beatmap, offset, &[*divisor])
+                    .is_some_and(|(time, _)| (time - offset as f64).abs() <= SNAP_TOLERANCE)
+            })
+            .map_or(2 + OFFBEAT_DIVISORS.len(), |v| 2 + v)
+    }
+
+    fn shorten_long_notes(&self, beatmap: &mut Beatmap) -> io::Result<()> {
+        if let Some(max_beats) = self.max_long_note_beats {
+            let objects = beatmap
+                .objects
+                .iter()
+                .map(|object| match *object {
+                    Object::LongNote {
+                        column,
+                        offset,
+                        end_offset,
+                    } => {
+                        let beat_length = beatmap
+                            .bpm_time_point_at(offset)
+                            .map_or(f32::INFINITY, |v| v.beat_length());
+                        let max_end = offset as f32 + beat_length * max_beats;
+                        Object::LongNote {
+                            column,
+                            offset,
+                            end_offset: (end_offset as f32).min(max_end) as i32,
+                        }
+                    }
+                    v => v,
+                })
+                .collect();
+            beatmap.objects = objects;
+        }
+        let mode = match self.remove_long_notes {
+            true => LongNoteMode::Remove,
+            false => LongNoteMode::Keep,
+        };
+        LongNotes {
+            mode,
+            min_gap_divisor: Some(2),
+            min_length_divisor: Some(4),
+        }
+        .apply(beatmap)
+    }
+
+    pub fn apply_to_beatmap(&self, beatmap: &Beatmap) -> io::Result<Beatmap> {
+        let mut easier = beatmap.clone();
+        easier.objects.sort_by_key(|v| (v.offset(), v.column()));
+
+        // Rank every object by the strength of its beat and its place in its chord.
+        let mut ranks = Vec::<(usize, usize)>::with_capacity(easier.objects.len());
+        let mut chord_rank = 0;
+        for (idx, object) in easier.objects.iter().enumerate() {
+            if idx > 0 && easier.objects[idx - 1].offset() == object.offset() {
+                chord_rank += 1;
+            } else {
+                chord_rank = 0;
+            }
+            let level = Self::strength_level(&easier, object.offset());
+            ranks.push((level + 2 * chord_rank, idx));
+        }
+        // The weakest first, and the later first among equals.
+        ranks.sort_by(|a, b| b.cmp(a));
+
+        // Find the fewest objects to remove by bisection.
+        let objects = std::mem::take(&mut easier.objects);
+        let keep = |removed_count: usize| {
+            let mut removed = vec![false; objects.len()];
+            for (_, idx) in &ranks[..removed_count] {
+                removed[*idx] = true;
+            }
+            objects
+                .iter()
+                .zip(removed)
+                .filter(|(_, removed)| !*removed)
+                .map(|(v, _)| *v)
+                .collect::<Vec<_>>()
+        };
+        let (mut low, mut high) = (0, objects.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            easier.objects = keep(middle);
+            if self.target.measure(&easier) <= self.target.value() {
+                high = middle;
+            } else {
+                low = middle + 1;
+            }
+        }
+        easier.objects = keep(low);
+
+        self.shorten_long_notes(&mut easier)?;
+        easier.version = Some(match (&self.version, &beatmap.version) {
+            (Some(version), _) => version.clone(),
+            (None, Some(version)) => format!("{} {}", version, self.target),
+            (None, None) => self.target.to_string(),
+        });
+        easier.online_ids.clear();
+        Ok(easier)
+    }
+
+    /// Append an easier copy of the hardest beatmap by the target measure.
+    pub fn apply(&self, package: &mut Package) -> io::Result<()> {
+        let hardest = package
+            .beatmaps
+            .iter()
+            .max_by(|a, b| self.target.measure(a).total_cmp(&self.target.measure(b)));
+        let Some(hardest) = hardest else {
+            return Ok(());
+        };
+        let easier = self.apply_to_beatmap(hardest)?;
+        package.beatmaps.push(easier);
+        Ok(())
+    }
+}
+
+impl Transform for Downscale {
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        self.apply(package)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, TimeSignature};
+
+    #[test]
+    fn downscale_density() {
+        let mut beatmap = Beatmap::new();
+        beatmap.version = Some("Insane".to_owned());
+        beatmap.column_count = Some(4);
+        // A beat is 500 ms, and a bar is 2000 ms.
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        // 1/4 stream with a chord on every downbeat for 8 bars.
+        for idx in 0..128 {
+            let offset = idx * 125;
+            beatmap.objects.push(Object::Note {
+                column: (idx % 4) as u32,
+                offset,
+            });
+            if idx % 16 == 0 {
+                beatmap.objects.push(Object::Note {
+                    column: ((idx + 2) % 4) as u32,
+                    offset,
+                });
+            }
+        }
+
+        let easier = Downscale::new(Target::Density(2.0))
+            .apply_to_beatmap(&beatmap)
+            .unwrap();
+        assert_eq!(easier.version.as_deref(), Some("Insane 2 NPS"));
+        assert!(density(&easier) <= 2.0);
+        assert!(density(&easier) > 1.5);
+        // Only beats are left, and downbeats are single notes.
+        assert!(easier.objects.iter().all(|v| v.offset() % 500 == 0));
+        assert!(easier.objects.iter().any(|v| v.offset() % 2000 == 0));
+        assert!(easier
+            .objects
+            .windows(2)
+            .all(|v| v[0].offset() != v[1].offset()));
+    }
+}
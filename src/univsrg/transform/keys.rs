@@ -0,0 +1,227 @@
+use std::io;
+
+use super::super::{
+    traits::BeatmapTransform,
+    types::{Beatmap, Layout, LayoutPreset, Object},
+};
+
+/// Notes lost by [ConvertKeys].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyConversionReport {
+    pub dropped_notes: usize,
+    pub dropped_long_notes: usize,
+}
+
+impl KeyConversionReport {
+    pub fn dropped(&self) -> usize {
+        self.dropped_notes + self.dropped_long_notes
+    }
+}
+
+/// Convert a beatmap to another number of columns.
+///
+/// Every column is mapped to a range of the new columns in proportion.
+/// When reducing, chords are shrunk and a note is moved to the nearest free column,
+/// or dropped if there is none, so long notes never overlap.
+/// When expanding, notes of a column take turns in its range of new columns.
+pub struct ConvertKeys {
+    pub column_count: u32,
+}
+
+impl ConvertKeys {
+    pub fn new(column_count: u32) -> Self {
+        Self { column_count }
+    }
+
+    pub fn apply(&self, beatmap: &mut Beatmap) -> io::Result<KeyConversionReport> {
+        let old_count = beatmap.checked_column_count()?;
+        let new_count = self.column_count;
+        if new_count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Column count should be positive.",
+            ));
+        }
+        let mut report = KeyConversionReport::default();
+
+        // The range of new columns for each old column.
+        let ranges = (0..old_count)
+            .map(|c| {
+                let begin = (c * new_count / old_count).min(new_count - 1);
+                let end = ((c + 1) * new_count / old_count).clamp(begin + 1, new_count);
+                begin..end
+            })
+            .collect::<Vec<_>>();
+        let mut turns = vec![0u32; old_count as usize];
+        // The end of the last object of each new column.
+        let mut busy_until = vec![i32::MIN; new_count as usize];
+
+        let mut objects = std::mem::take(&mut beatmap.objects);
+        objects.sort_by_key(|v| (v.offset(), v.column()));
+        let mut converted = Vec::<Object>::with_capacity(objects.len());
+        let mut idx = 0;
+        while idx < objects.len() {
+            let offset = objects[idx].offset();
+            let chord_end = objects[idx..]
+                .iter()
+                .position(|v| v.offset() != offset)
+                .map_or(objects.len(), |v| idx + v);
+            let mut used = Vec::<u32>::new();
+            for object in &objects[idx..chord_end] {
+                let is_free = |c: u32| !used.contains(&c) && busy_until[c as usize] < offset;
+                let range = ranges[object.column() as usize].clone();
+                let turn = &mut turns[object.column() as usize];
+                let preferred = range
+                    .clone()
+                    .cycle()
+                    .skip((*turn % range.len() as u32) as usize)
+                    .take(range.len())
+                    .find(|c| is_free(*c));
+                let column = preferred.or_else(|| {
+                    // The nearest free column.
+                    (0..new_count)
+                        .filter(|c| is_free(*c))
+                        .min_by_key(|c| (*c as i64 - range.start as i64).abs())
+                });
+                let Some(column) = column else {
+                    match object {
+                        Object::Note { .. } => report.dropped_notes += 1,
+                        Object::LongNote { .. } => report.dropped_long_notes += 1,
+                    }
+                    continue;
+                };
+                *turn += 1;
+                used.push(column);
+                busy_until[column as usize] = object.end_offset().unwrap_or(offset);
+                converted.push(match *object {
+                    Object::Note { offset, .. } => Object::Note { column, offset },
+                    Object::LongNote {
+                        offset, end_offset, ..
+                    } => Object::LongNote {
+                        column,
+                        offset,
+                        end_offset,
+                    },
+                });
+            }
+            idx = chord_end;
+        }
+
+        beatmap.objects = converted;
+        beatmap.column_count = Some(new_count);
+        // Special columns do not survive the conversion.
+        if beatmap.layout.is_some() {
+            beatmap.layout = Some(Layout::new(LayoutPreset::Keys, new_count));
+        }
+        Ok(report)
+    }
+}
+
+impl BeatmapTransform for ConvertKeys {
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>> {
+        let report = self.apply(beatmap)?;
+        Ok(match report.dropped() {
+            0 => vec![],
+            _ => vec![format!(
+                "{} notes and {} long notes are dropped.",
+                report.dropped_notes, report.dropped_long_notes
+            )],
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn has_overlap(beatmap: &Beatmap) -> bool {
+        let column_count = beatmap.column_count.unwrap() as usize;
+        let mut busy_until = vec![i32::MIN; column_count];
+        let mut objects = beatmap.objects.clone();
+        objects.sort_by_key(|v| v.offset());
+        for object in objects {
+            let column = object.column() as usize;
+            if busy_until[column] >= object.offset() {
+                return true;
+            }
+            busy_until[column] = object.end_offset().unwrap_or(object.offset());
+        }
+        false
+    }
+
+    #[test]
+    fn convert_keys_reduce() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(7);
+        // A 7-note chord, then a long note under a 4-note chord.
+        for column in 0..7 {
+            beatmap.objects.push(Object::Note { column, offset: 0 });
+        }
+        beatmap.objects.push(Object::LongNote {
+            column: 0,
+            offset: 100,
+            end_offset: 500,
+        });
+        for column in 3..7 {
+            beatmap.objects.push(Object::Note {
+                column,
+                offset: 200,
+            });
+        }
+
+        let report = ConvertKeys::new(4).apply(&mut beatmap).unwrap();
+        assert_eq!(beatmap.column_count, Some(4));
+        assert!(beatmap.objects.iter().all(|v| v.column() < 4));
+        assert!(!has_overlap(&beatmap));
+        assert_eq!(report.dropped_notes, 3 + 1);
+        assert_eq!(report.dropped_long_notes, 0);
+        assert_eq!(beatmap.objects.len() + report.dropped(), 12);
+    }
+
+    #[test]
+    fn convert_keys_warning() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        for column in 0..4 {
+            beatmap.objects.push(Object::Note { column, offset: 0 });
+        }
+        let warnings = ConvertKeys::new(2).transform_beatmap(&mut beatmap).unwrap();
+        assert_eq!(warnings, vec!["2 notes and 0 long notes are dropped."]);
+        let warnings = ConvertKeys::new(4).transform_beatmap(&mut beatmap).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn convert_keys_expand() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        for idx in 0..16 {
+            beatmap.objects.push(Object::Note {
+                column: idx % 4,
+                offset: idx as i32 * 100,
+            });
+        }
+
+        let report = ConvertKeys::new(8).apply(&mut beatmap).unwrap();
+        assert_eq!(report.dropped(), 0);
+        // Every new column is used.
+        for column in 0..8 {
+            assert!(beatmap.objects.iter().any(|v| v.column() == column));
+        }
+    }
+
+    #[test]
+    fn convert_keys_invalid_columns() {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(0);
+        assert!(ConvertKeys::new(4).apply(&mut beatmap).is_err());
+
+        beatmap.column_count = Some(4);
+        beatmap.objects.push(Object::Note {
+            column: 4,
+            offset: 0,
+        });
+        assert!(ConvertKeys::new(7).apply(&mut beatmap).is_err());
+        assert_eq!(beatmap.objects.len(), 1);
+    }
+}
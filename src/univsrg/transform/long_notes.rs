@@ -0,0 +1,284 @@
+use std::io;
+
+use super::super::{
+    traits::BeatmapTransform,
+    types::{Beatmap, Object},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LongNoteMode {
+    /// Only enforce the gaps and the lengths.
+    Keep,
+    /// Hold every note up to the next note in its column.
+    Full,
+    /// Swap holds and gaps, i.e. hold from both the head and the tail of every hold,
+    /// and from every tap, up to the next of them in the column.
+    Inverse,
+    /// Turn holds into taps.
+    Remove,
+}
+
+/// Transform long notes of a beatmap.
+///
+/// Gaps and lengths are in beats divided by a snap divisor, e.g. `4` for 1/4 beat,
+/// using the BPM in effect at the point.
+/// The last note of a column has nothing to hold up to, so [LongNoteMode::Full] and
+/// [LongNoteMode::Inverse] leave it as is.
+pub struct LongNotes {
+    pub mode: LongNoteMode,
+    /// Minimum gap between the end of a hold and the next note in its column.
+    /// Holds always end before the next note.
+    pub min_gap_divisor: Option<u32>,
+    /// Holds shorter than this become taps.
+    pub min_length_divisor: Option<u32>,
+}
+
+impl LongNotes {
+    pub fn new(mode: LongNoteMode) -> Self {
+        Self {
+            mode,
+            min_gap_divisor: Some(4),
+            min_length_divisor: None,
+        }
+    }
+
+    fn snap_length(beatmap: &Beatmap, offset: i32, divisor: Option<u32>) -> io::Result<i32> {
+        let Some(divisor) = divisor else {
+            return Ok(0);
+        };
+        if divisor == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Snap divisor should be positive.",
+            ));
+        }
+        let btp = beatmap.bpm_time_point_at(offset).ok_or(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "No BPM time point.",
+        ))?;
+        Ok((btp.beat_length() / divisor as f32).round() as i32)
+    }
+
+    pub fn apply(&self, beatmap: &mut Beatmap) -> io::Result<()> {
+        let mut objects = std::mem::take(&mut beatmap.objects);
+        if self.mode == LongNoteMode::Remove {
+            // No gaps or lengths to compute, so no BPM is needed.
+            beatmap.objects = objects
+                .into_iter()
+                .map(|v| Object::Note {
+                    column: v.column(),
+                    offset: v.offset(),
+                })
+                .collect();
+            beatmap.objects.sort_by_key(|v| (v.offset(), v.column()));
+            return Ok(());
+        }
+        if self.mode == LongNoteMode::Inverse {
+            // Heads and tails both start a hold, as the Invert mod of osu!lazer does.
+            objects = objects
+                .iter()
+                .flat_map(|v| {
+                    let column = v.column();
+                    std::iter::once(v.offset())
+                        .chain(v.end_offset())
+                        .map(move |offset| Object::Note { column, offset })
+                })
+                .collect();
+        }
+        objects.sort_by_key(|v| (v.column(), v.offset()));
+        if self.mode == LongNoteMode::Inverse {
+            // A tail may touch the next head.
+            objects.dedup();
+        }
+
+        let mut transformed = Vec::<Object>::with_capacity(objects.len());
+        for (idx, object) in objects.iter().enumerate() {
+            let column = object.column();
+            let offset = object.offset();
+            let next = objects
+                .get(idx + 1)
+                .filter(|v| v.column() == column)
+                .map(|v| v.offset());
+            let gap = match next {
+                Some(next) => Self::snap_length(beatmap, next, self.min_gap_divisor)?.max(1),
+                None => 0,
+            };
+
+            let end_offset = match (self.mode, next) {
+                (LongNoteMode::Full | LongNoteMode::Inverse, Some(next)) => Some(next - gap),
+                _ => object.end_offset(),
+            };
+            let end_offset = end_offset.map(|v| match next {
+                Some(next) => v.min(next - gap),
+                None => v,
+            });
+            let min_length = Self::snap_length(beatmap, offset, self.min_length_divisor)?;
+            transformed.push(match end_offset {
+                Some(end_offset) if end_offset > offset && end_offset - offset >= min_length => {
+                    Object::LongNote {
+                        column,
+                        offset,
+                        end_offset,
+                    }
+                }
+                _ => Object::Note { column, offset },
+            });
+        }
+
+        transformed.sort_by_key(|v| (v.offset(), v.column()));
+        beatmap.objects = transformed;
+        Ok(())
+    }
+}
+
+impl BeatmapTransform for LongNotes {
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>> {
+        self.apply(beatmap)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, TimeSignature};
+
+    /// 120 BPM, so a 1/4 beat is 125 ms.
+    fn beatmap(objects: Vec<Object>) -> Beatmap {
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects = objects;
+        beatmap
+    }
+
+    #[test]
+    fn long_notes_full_and_inverse() {
+        let objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 0,
+            },
+            Object::LongNote {
+                column: 0,
+                offset: 1000,
+                end_offset: 1500,
+            },
+            Object::Note {
+                column: 0,
+                offset: 2000,
+            },
+        ];
+
+        let mut full = beatmap(objects.clone());
+        LongNotes::new(LongNoteMode::Full).apply(&mut full).unwrap();
+        assert_eq!(
+            full.objects,
+            vec![
+                Object::LongNote {
+                    column: 0,
+                    offset: 0,
+                    end_offset: 875,
+                },
+                Object::LongNote {
+                    column: 0,
+                    offset: 1000,
+                    end_offset: 1875,
+                },
+                Object::Note {
+                    column: 0,
+                    offset: 2000,
+                },
+            ]
+        );
+
+        let mut inverse = beatmap(objects);
+        LongNotes::new(LongNoteMode::Inverse)
+            .apply(&mut inverse)
+            .unwrap();
+        assert_eq!(
+            inverse.objects,
+            vec![
+                Object::LongNote {
+                    column: 0,
+                    offset: 0,
+                    end_offset: 875,
+                },
+                Object::LongNote {
+                    column: 0,
+                    offset: 1000,
+                    end_offset: 1375,
+                },
+                Object::LongNote {
+                    column: 0,
+                    offset: 1500,
+                    end_offset: 1875,
+                },
+                Object::Note {
+                    column: 0,
+                    offset: 2000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn long_notes_enforce_and_remove() {
+        let objects = vec![
+            // Too short for 1/2 beat.
+            Object::LongNote {
+                column: 1,
+                offset: 0,
+                end_offset: 200,
+            },
+            // Too close to the next note.
+            Object::LongNote {
+                column: 1,
+                offset: 500,
+                end_offset: 1450,
+            },
+            Object::Note {
+                column: 1,
+                offset: 1500,
+            },
+        ];
+
+        let mut enforced = beatmap(objects.clone());
+        LongNotes {
+            min_length_divisor: Some(2),
+            ..LongNotes::new(LongNoteMode::Keep)
+        }
+        .apply(&mut enforced)
+        .unwrap();
+        assert_eq!(
+            enforced.objects[..2],
+            [
+                Object::Note {
+                    column: 1,
+                    offset: 0,
+                },
+                Object::LongNote {
+                    column: 1,
+                    offset: 500,
+                    end_offset: 1375,
+                },
+            ]
+        );
+
+        // Removing needs no BPM.
+        let mut removed = beatmap(objects);
+        removed.bpm_time_points.clear();
+        LongNotes::new(LongNoteMode::Remove)
+            .apply(&mut removed)
+            .unwrap();
+        assert!(removed
+            .objects
+            .iter()
+            .all(|v| matches!(v, Object::Note { .. })));
+        assert_eq!(removed.objects.len(), 3);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod columns;
+pub mod downscale;
+pub mod keys;
+pub mod long_notes;
+pub mod offset;
+pub mod pipeline;
+pub mod rate;
+pub mod scroll;
+pub mod snap;
+pub mod trim;
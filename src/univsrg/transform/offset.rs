@@ -0,0 +1,211 @@
+use std::{collections::HashMap, io};
+
+use super::super::{
+    audio::Audio,
+    resource::ResourceEntry,
+    traits::Transform,
+    types::{Beatmap, Package},
+};
+
+/// Samples quieter than this, about -60 dB, are silence.
+const SILENCE_THRESHOLD: f32 = 1e-3;
+
+/// Shift every object and time point of every beatmap by `offset` ms, i.e. later if positive.
+///
+/// If the first object would come earlier than `lead_in` ms, silence is padded at the start
+/// of the audio, and everything is moved later with the audio to keep them in sync.
+/// Beatmaps sharing the audio are moved together.
+/// Storyboards stay where they are, so they may go out of sync when the audio changes.
+pub struct ShiftOffset {
+    pub offset: i32,
+    /// Minimum time from the start of the audio to the first object.
+    pub lead_in: i32,
+    /// Also trim silence at the start of the audio that comes before `lead_in`.
+    pub trim_silence: bool,
+}
+
+impl ShiftOffset {
+    pub fn new(offset: i32) -> Self {
+        Self {
+            offset,
+            lead_in: 0,
+            trim_silence: false,
+        }
+    }
+
+    /// The first object after the shift.
+    fn first_offset(&self, beatmap: &Beatmap) -> Option<i32> {
+        beatmap
+            .objects
+            .iter()
+            .map(|v| v.offset() + self.offset)
+            .min()
+    }
+
+    /// Move `beatmap` with its audio, which is padded by `ms` or trimmed if negative.
+    /// The storyboard is not moved, since it is timed against the audio.
+    fn follow_audio(beatmap: &mut Beatmap, ms: i32) {
+        if ms == 0 {
+            return;
+        }
+        beatmap.map_offsets(|v| v + ms);
+        if ms > 0 {
+            // Silence in the audio takes the place of the lead-in.
+            beatmap.audio_lead_in = beatmap.audio_lead_in.map(|v| (v - ms).max(0));
+        }
+    }
+
+    pub fn apply(&self, package: &mut Package) -> io::Result<()> {
+        let mut groups = HashMap::<ResourceEntry, Vec<usize>>::new();
+        for (idx, beatmap) in package.beatmaps.iter().enumerate() {
+            if let Some(audio) = &beatmap.audio {
+                groups.entry(audio.clone()).or_default().push(idx);
+            }
+        }
+
+        // Process the audio before changing anything, so a failure leaves the package as is.
+        let mut shifts = Vec::new();
+        for (entry, indices) in groups {
+            let lack = indices
+                .iter()
+                .filter_map(|idx| self.first_offset(&package.beatmaps[*idx]))
+                .map(|v| self.lead_in - v)
+                .max();
+            let Some(lack) = lack else {
+                continue;
+            };
+            if lack <= 0 && !self.trim_silence {
+                continue;
+            }
+
+            let extension = entry.original_path.extension().and_then(|v| v.to_str());
+            let audio = Audio::decode(&entry.bytes, extension)?;
+            let (audio, ms) = if lack > 0 {
+                (audio.pad_start(lack as f64), lack)
+            } else {
+                let ms = (audio.leading_silence_ms(SILENCE_THRESHOLD) as i32).min(-lack);
+                if ms == 0 {
+                    continue;
+                }
+                (audio.trim_start(ms as f64), -ms)
+            };
+            let shifted = ResourceEntry::new(
+                entry.original_path.with_extension("wav"),
+                audio.encode_wav()?,
+            );
+            shifts.push((entry, indices, ms, shifted));
+        }
+
+        for beatmap in &mut package.beatmaps {
+            let lack = match beatmap.audio {
+                Some(_) => 0,
+                None => self.first_offset(beatmap).map_or(0, |v| self.lead_in - v),
+            };
+            // The video plays along with the audio, not the objects.
+            let video_offset = beatmap.video.as_ref().map(|v| v.offset);
+            beatmap.map_offsets(|v| v + self.offset);
+            if let (Some(video), Some(offset)) = (&mut beatmap.video, video_offset) {
+                video.offset = offset;
+            }
+            Self::follow_audio(beatmap, lack.max(0));
+        }
+        for (entry, indices, ms, shifted) in shifts {
+            for idx in indices {
+                let beatmap = &mut package.beatmaps[idx];
+                Self::follow_audio(beatmap, ms);
+                beatmap.audio = Some(shifted.clone());
+            }
+            package.resource_pool.insert(shifted);
+            package.remove_unreferenced([entry]);
+        }
+        Ok(())
+    }
+}
+
+impl Transform for ShiftOffset {
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        self.apply(package)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::Object;
+
+    fn package(audio: &Audio, first_offset: i32) -> Package {
+        let entry = ResourceEntry::new("audio.wav".into(), audio.encode_wav().unwrap());
+        let mut package = Package::new();
+        package.resource_pool.insert(entry.clone());
+        let mut beatmap = Beatmap::new();
+        beatmap.audio = Some(entry);
+        beatmap.audio_lead_in = Some(500);
+        beatmap.preview_time = Some(first_offset);
+        beatmap.objects.push(Object::Note {
+            column: 0,
+            offset: first_offset,
+        });
+        package.beatmaps.push(beatmap);
+        package
+    }
+
+    fn decoded(beatmap: &Beatmap) -> Audio {
+        Audio::decode(&beatmap.audio.as_ref().unwrap().bytes, Some("wav")).unwrap()
+    }
+
+    #[test]
+    fn shift_offset_pad() {
+        let audio = Audio {
+            sample_rate: 1000,
+            channel_count: 1,
+            samples: vec![0.5; 1000],
+        };
+        let mut package = package(&audio, 100);
+        ShiftOffset::new(-300).apply(&mut package).unwrap();
+
+        let beatmap = &package.beatmaps[0];
+        assert_eq!(beatmap.objects[0].offset(), 0);
+        assert_eq!(beatmap.preview_time, Some(0));
+        assert_eq!(beatmap.audio_lead_in, Some(300));
+        assert!((decoded(beatmap).leading_silence_ms(SILENCE_THRESHOLD) - 200.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn shift_offset_trim_silence() {
+        let audio = Audio {
+            sample_rate: 1000,
+            channel_count: 1,
+            samples: [vec![0.0; 400], vec![0.5; 600]].concat(),
+        };
+        let mut package = package(&audio, 1000);
+        ShiftOffset {
+            lead_in: 100,
+            trim_silence: true,
+            ..ShiftOffset::new(0)
+        }
+        .apply(&mut package)
+        .unwrap();
+
+        let beatmap = &package.beatmaps[0];
+        assert_eq!(beatmap.objects[0].offset(), 600);
+        assert_eq!(beatmap.audio_lead_in, Some(500));
+        assert!((decoded(beatmap).duration_ms() - 600.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn shift_offset_bad_audio() {
+        let audio = Audio {
+            sample_rate: 1000,
+            channel_count: 1,
+            samples: vec![0.5; 1000],
+        };
+        let mut package = package(&audio, 100);
+        let entry = ResourceEntry::new("audio.wav".into(), b"not audio".to_vec());
+        package.resource_pool.insert(entry.clone());
+        package.beatmaps[0].audio = Some(entry);
+        assert!(ShiftOffset::new(-300).apply(&mut package).is_err());
+        // Nothing moves when the audio fails.
+        assert_eq!(package.beatmaps[0].objects[0].offset(), 100);
+    }
+}
@@ -0,0 +1,261 @@
+//! Chaining transforms from specs like `mirror`, `rate=1.2` or `snap=1/4`.
+
+use std::{io, str::FromStr};
+
+use super::{
+    super::{traits::Transform, types::Package},
+    columns::{Permutation, PermuteColumns},
+    downscale::{Downscale, Target},
+    keys::ConvertKeys,
+    long_notes::{LongNoteMode, LongNotes},
+    offset::ShiftOffset,
+    rate::{Rate, StretchMode},
+    scroll::{NormalizeScroll, ScrollMode},
+    snap::{Snap, DEFAULT_DIVISORS},
+    trim::Trim,
+};
+
+/// Specs of the transforms, shown when a spec is not understood.
+pub const SPECS: &str = "mirror, permute=<columns>, random[=<seed>], s-random[=<seed>], \
+    mirror-keys, random-keys[=<seed>], s-random-keys[=<seed>], \
+    keys=<count>, full-ln, inverse-ln, no-ln, ln-gap=1/<divisor>, \
+    strip-sv, constant-sv[=<bpm>], scaled-sv[=<bpm>], \
+    rate=<rate>[,replace], nc=<rate>[,replace], offset=<ms>, snap[=1/<divisor>,...], \
+    trim=<start>-<end>[,replace], downscale=<nps>, downscale=<stars>*";
+
+fn invalid(spec: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Unknown transform \"{}\". Try {}.", spec, SPECS),
+    )
+}
+
+fn parse<T: FromStr>(spec: &str, value: &str) -> io::Result<T> {
+    value.trim().parse::<T>().map_err(|_| invalid(spec))
+}
+
+/// Parse `1/4` to 4.
+fn parse_divisor(spec: &str, value: &str) -> io::Result<u32> {
+    let divisor = value.trim().strip_prefix("1/").ok_or(invalid(spec))?;
+    parse(spec, divisor)
+}
+
+/// Parse `1:20.5` or `80500` to milliseconds.
+fn parse_time(spec: &str, value: &str) -> io::Result<i32> {
+    match value.split_once(':') {
+        Some((minutes, seconds)) => {
+            let minutes = parse::<i32>(spec, minutes)?;
+            let seconds = parse::<f64>(spec, seconds)?;
+            Ok(minutes * 60000 + (seconds * 1000.0).round() as i32)
+        }
+        None => parse(spec, value),
+    }
+}
+
+/// Parse `1.2,replace` to `1.2` and whether to replace the beatmaps.
+fn split_replace<'a>(spec: &str, value: &'a str) -> io::Result<(&'a str, bool)> {
+    match value.split_once(',') {
+        Some((value, "replace")) => Ok((value, true)),
+        Some(_) => Err(invalid(spec)),
+        None => Ok((value, false)),
+    }
+}
+
+fn random_seed(spec: &str, value: Option<&str>) -> io::Result<u64> {
+    match value {
+        Some(value) => parse(spec, value),
+        None => Ok(rand::random()),
+    }
+}
+
+/// Make a transform from a spec like `rate=1.2`.
+pub fn parse_transform(spec: &str) -> io::Result<Box<dyn Transform>> {
+    let (name, value) = match spec.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value)),
+        None => (spec.trim(), None),
+    };
+    let required = || value.ok_or(invalid(spec));
+    Ok(match name {
+        // The `-keys` variants keep scratch columns in place.
+        "mirror" | "mirror-keys" => Box::new(PermuteColumns {
+            keep_scratch: name.ends_with("-keys"),
+            ..PermuteColumns::new(Permutation::Mirror)
+        }),
+        "permute" => {
+            let columns = required()?
+                .split(',')
+                .map(|v| parse(spec, v))
+                .collect::<io::Result<Vec<u32>>>()?;
+            Box::new(PermuteColumns::new(Permutation::Fixed(columns)))
+        }
+        "random" | "random-keys" => Box::new(PermuteColumns {
+            keep_scratch: name.ends_with("-keys"),
+            ..PermuteColumns::new(Permutation::Random {
+                seed: random_seed(spec, value)?,
+            })
+        }),
+        "s-random" | "s-random-keys" => Box::new(PermuteColumns {
+            keep_scratch: name.ends_with("-keys"),
+            ..PermuteColumns::new(Permutation::SRandom {
+                seed: random_seed(spec, value)?,
+                min_jack_interval: 100,
+            })
+        }),
+        "keys" => Box::new(ConvertKeys::new(parse(spec, required()?)?)),
+        "full-ln" => Box::new(LongNotes::new(LongNoteMode::Full)),
+        "inverse-ln" => Box::new(LongNotes::new(LongNoteMode::Inverse)),
+        "no-ln" => Box::new(LongNotes::new(LongNoteMode::Remove)),
+        "ln-gap" => Box::new(LongNotes {
+            min_gap_divisor: Some(parse_divisor(spec, required()?)?),
+            ..LongNotes::new(LongNoteMode::Keep)
+        }),
+        "strip-sv" | "constant-sv" | "scaled-sv" => Box::new(NormalizeScroll {
+            mode: match name {
+                "strip-sv" => ScrollMode::Strip,
+                "constant-sv" => ScrollMode::Constant,
+                _ => ScrollMode::BpmScaled,
+            },
+            base_bpm: value.map(|v| parse(spec, v)).transpose()?,
+        }),
+        "rate" | "nc" => {
+            let (rate, replace) = split_replace(spec, required()?)?;
+            let mode = match name {
+                "rate" => StretchMode::TimeStretch,
+                _ => StretchMode::Resample,
+            };
+            Box::new(Rate {
+                replace,
+                ..Rate::new(parse(spec, rate)?, mode)
+            })
+        }
+        "offset" => Box::new(ShiftOffset::new(parse(spec, required()?)?)),
+        "snap" => Box::new(Snap::new(match value {
+            // Every divisor of the given ones, e.g. 1/1, 1/2 and 1/4 for 1/4.
+            Some(value) => {
+                let limits = value
+                    .split(',')
+                    .map(|v| parse_divisor(spec, v))
+                    .collect::<io::Result<Vec<_>>>()?;
+                (1..=limits.iter().copied().max().unwrap_or(1))
+                    .filter(|d| limits.iter().any(|limit| limit % d == 0))
+                    .collect()
+            }
+            None => DEFAULT_DIVISORS.to_vec(),
+        })),
+        "trim" => {
+            let (range, replace) = split_replace(spec, required()?)?;
+            let (start, end) = range.split_once('-').ok_or(invalid(spec))?;
+            Box::new(Trim {
+                replace,
+                ..Trim::new(parse_time(spec, start)?, parse_time(spec, end)?)
+            })
+        }
+        "downscale" => {
+            let value = required()?;
+            Box::new(Downscale::new(match value.strip_suffix('*') {
+                Some(stars) => Target::StarRating(parse(spec, stars)?),
+                None => Target::Density(parse(spec, value)?),
+            }))
+        }
+        _ => return Err(invalid(spec)),
+    })
+}
+
+/// Transforms run in order.
+///
+/// Most steps change the beatmaps in place. `rate`, `nc` and `trim` add copies next to the
+/// originals, or change them in place with `,replace`, so that `rate=1.1,replace` and then
+/// `rate=1.2,replace` play at 1.32x. `downscale` adds a beatmap made from the hardest one.
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<(String, Box<dyn Transform>)>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self { steps: vec![] }
+    }
+
+    pub fn from_specs(specs: &[String]) -> io::Result<Self> {
+        let mut pipeline = Self::new();
+        for spec in specs {
+            pipeline.push(spec.clone(), parse_transform(spec)?);
+        }
+        Ok(pipeline)
+    }
+
+    pub fn push(&mut self, name: String, transform: Box<dyn Transform>) {
+        self.steps.push((name, transform));
+    }
+
+    /// Run every step. The warnings and the error tell which step they come from.
+    pub fn run(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        let mut warnings = vec![];
+        for (idx, (name, transform)) in self.steps.iter().enumerate() {
+            let step_warnings = transform.transform(package).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Step {} ({}) failed: {}", idx + 1, name, e),
+                )
+            })?;
+            warnings.extend(
+                step_warnings
+                    .into_iter()
+                    .map(|v| format!("Step {} ({}): {}", idx + 1, name, v)),
+            );
+        }
+        Ok(warnings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{Beatmap, Object};
+
+    #[test]
+    fn pipeline_run() {
+        let mut package = Package::new();
+        let mut beatmap = Beatmap::new();
+        beatmap.column_count = Some(4);
+        beatmap.objects.push(Object::Note {
+            column: 0,
+            offset: 1000,
+        });
+        package.beatmaps.push(beatmap);
+
+        let specs = ["mirror", "offset=-200", "keys=7"].map(String::from);
+        Pipeline::from_specs(&specs)
+            .unwrap()
+            .run(&mut package)
+            .unwrap();
+        assert_eq!(package.beatmaps[0].column_count, Some(7));
+        assert_eq!(package.beatmaps[0].objects[0].offset(), 800);
+
+        let specs = ["mirror", "snap=1/4"].map(String::from);
+        let error = Pipeline::from_specs(&specs)
+            .unwrap()
+            .run(&mut package)
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Step 2 (snap=1/4) failed"));
+        // Rates compose when replacing.
+        let specs = ["rate=1.1,replace", "rate=1.2,replace"].map(String::from);
+        Pipeline::from_specs(&specs)
+            .unwrap()
+            .run(&mut package)
+            .unwrap();
+        assert_eq!(package.beatmaps.len(), 1);
+        assert_eq!(package.beatmaps[0].objects[0].offset(), 606);
+        let specs = ["rate=1.2"].map(String::from);
+        Pipeline::from_specs(&specs)
+            .unwrap()
+            .run(&mut package)
+            .unwrap();
+        assert_eq!(package.beatmaps.len(), 2);
+        assert!(parse_transform("rate=1.2,keep").is_err());
+        assert!(parse_transform("rate").is_err());
+        assert!(parse_transform("trim=1:20-1:45.5").is_ok());
+        assert!(parse_transform("trim=1:20-1:45.5,replace").is_ok());
+        assert!(parse_transform("s-random-keys=1").is_ok());
+    }
+}
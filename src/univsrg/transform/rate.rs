@@ -0,0 +1,225 @@
+use std::{collections::HashMap, io};
+
+use super::super::{
+    audio::Audio,
+    resource::ResourceEntry,
+    traits::Transform,
+    types::{Beatmap, Package},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StretchMode {
+    /// Keep the pitch.
+    TimeStretch,
+    /// Change the pitch with the speed, like nightcore.
+    Resample,
+}
+
+/// Make a copy of every beatmap played at `rate`, with its audio stretched.
+///
+/// The copies are appended to the package, and their versions are suffixed with the rate,
+/// e.g. "Hard 1.2x", so that they are compiled to distinct difficulties.
+pub struct Rate {
+    pub rate: f32,
+    pub mode: StretchMode,
+    /// Replace the beatmaps with the copies instead, so that rates compose.
+    pub replace: bool,
+}
+
+impl Rate {
+    pub fn new(rate: f32, mode: StretchMode) -> Self {
+        Self {
+            rate,
+            mode,
+            replace: false,
+        }
+    }
+
+    fn suffix(&self) -> String {
+        match self.mode {
+            StretchMode::TimeStretch => format!("{}x", self.rate),
+            StretchMode::Resample => format!("{}x NC", self.rate),
+        }
+    }
+
+    /// Scale the timing of `beatmap`. The audio is left as is.
+    pub fn apply_to_beatmap(&self, beatmap: &Beatmap) -> Beatmap {
+        let rate = self.rate as f64;
+        let scale = |v: i32| (v as f64 / rate).round() as i32;
+
+        let mut beatmap = beatmap.clone();
+        beatmap.map_offsets(scale);
+        for btp in &mut beatmap.bpm_time_points {
+            btp.bpm *= self.rate;
+        }
+        beatmap.audio_lead_in = beatmap.audio_lead_in.map(scale);
+        beatmap.preview_duration = beatmap.preview_duration.map(scale);
+        beatmap.version = Some(match &beatmap.version {
+            Some(version) => format!("{} {}", version, self.suffix()),
+            None => self.suffix(),
+        });
+        // The copy is not the beatmap online.
+        beatmap.online_ids.clear();
+        // The video and the storyboard would play at the original speed.
+        beatmap.video = None;
+        beatmap.storyboard = Default::default();
+        beatmap
+    }
+
+    fn stretch_audio(&self, entry: &ResourceEntry) -> io::Result<ResourceEntry> {
+        let extension = entry.original_path.extension().and_then(|v| v.to_str());
+        let audio = Audio::decode(&entry.bytes, extension)?;
+        let audio = match self.mode {
+            StretchMode::TimeStretch => audio.time_stretch(self.rate),
+            StretchMode::Resample => audio.resample(self.rate),
+        };
+        let stem = entry
+            .original_path
+            .file_stem()
+            .map(|v| v.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let path = entry
+            .original_path
+            .with_file_name(format!("{} {}.wav", stem, self.suffix()));
+        Ok(ResourceEntry::new(path, audio.encode_wav()?))
+    }
+
+    pub fn apply(&self, package: &mut Package) -> io::Result<()> {
+        if !(self.rate > 0.0 && self.rate.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Rate should be positive.",
+            ));
+        }
+        // Beatmaps of a set usually share the audio, so stretch it only once.
+        // Stretch everything before changing anything, so that an error leaves the package as is.
+        let mut stretched = HashMap::<ResourceEntry, ResourceEntry>::new();
+        for audio in package.beatmaps.iter().filter_map(|v| v.audio.as_ref()) {
+            if !stretched.contains_key(audio) {
+                stretched.insert(audio.clone(), self.stretch_audio(audio)?);
+            }
+        }
+        let copies = package
+            .beatmaps
+            .iter()
+            .map(|beatmap| {
+                let mut copy = self.apply_to_beatmap(beatmap);
+                copy.audio = beatmap
+                    .audio
+                    .as_ref()
+                    .and_then(|v| stretched.get(v).cloned());
+                copy
+            })
+            .collect::<Vec<_>>();
+        // The copies leave out the video and the storyboard, which may now be unused.
+        let media = package
+            .beatmaps
+            .iter()
+            .flat_map(|v| v.media_resources().cloned())
+            .collect::<Vec<_>>();
+        match self.replace {
+            true => package.beatmaps = copies,
+            false => package.beatmaps.extend(copies),
+        }
+        package.remove_unreferenced(media);
+        for (original, entry) in stretched {
+            package.resource_pool.insert(entry);
+            package.remove_unreferenced([original]);
+        }
+        Ok(())
+    }
+}
+
+impl Transform for Rate {
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        self.apply(package)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, Object, TimeSignature};
+
+    #[test]
+    fn rate_apply_to_beatmap() {
+        let mut beatmap = Beatmap::new();
+        beatmap.version = Some("Hard".to_owned());
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 120,
+            bpm: 100.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects.push(Object::LongNote {
+            column: 0,
+            offset: 1200,
+            end_offset: 2400,
+        });
+        beatmap.preview_time = Some(600);
+
+        let rated = Rate::new(1.2, StretchMode::TimeStretch).apply_to_beatmap(&beatmap);
+        assert_eq!(rated.version.as_deref(), Some("Hard 1.2x"));
+        assert_eq!(rated.bpm_time_points[0].offset, 100);
+        assert!((rated.bpm_time_points[0].bpm - 120.0).abs() < 1e-3);
+        assert_eq!(
+            rated.objects[0],
+            Object::LongNote {
+                column: 0,
+                offset: 1000,
+                end_offset: 2000,
+            }
+        );
+        assert_eq!(rated.preview_time, Some(500));
+        assert_ne!(rated.make_basename(), beatmap.make_basename());
+    }
+
+    #[test]
+    fn rate_apply() {
+        let audio = Audio {
+            sample_rate: 1000,
+            channel_count: 1,
+            samples: vec![0.5; 1200],
+        };
+        let entry = ResourceEntry::new("audio.wav".into(), audio.encode_wav().unwrap());
+        let mut package = Package::new();
+        package.resource_pool.insert(entry.clone());
+        let mut beatmap = Beatmap::new();
+        beatmap.version = Some("Hard".to_owned());
+        beatmap.audio = Some(entry.clone());
+        let script = ResourceEntry::new("storyboard.osb".into(), b"[Events]".to_vec());
+        package.resource_pool.insert(script.clone());
+        beatmap.storyboard.script = Some(script.clone());
+        package.beatmaps.push(beatmap);
+
+        // The original difficulty and its audio are kept.
+        Rate::new(1.2, StretchMode::TimeStretch)
+            .apply(&mut package)
+            .unwrap();
+        assert_eq!(package.beatmaps.len(), 2);
+        assert_eq!(package.beatmaps[0].version.as_deref(), Some("Hard"));
+        assert_eq!(package.beatmaps[0].audio.as_ref(), Some(&entry));
+        assert!(package.resource_pool.contains(&entry));
+        let copy = package.beatmaps[1].audio.clone().unwrap();
+        assert_ne!(copy, entry);
+        assert!(package.resource_pool.contains(&copy));
+        assert!(package.resource_pool.contains(&script));
+
+        Rate {
+            replace: true,
+            ..Rate::new(1.2, StretchMode::Resample)
+        }
+        .apply(&mut package)
+        .unwrap();
+        assert_eq!(package.beatmaps.len(), 2);
+        assert_eq!(package.beatmaps[0].version.as_deref(), Some("Hard 1.2x NC"));
+        assert!(!package.resource_pool.contains(&entry));
+        assert!(!package.resource_pool.contains(&copy));
+        // The storyboard is not used by the copies.
+        assert!(!package.resource_pool.contains(&script));
+
+        assert!(Rate::new(f32::NAN, StretchMode::TimeStretch)
+            .apply(&mut package)
+            .is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+
+use super::super::{
+    traits::BeatmapTransform,
+    types::{Beatmap, EffectTimePoint},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollMode {
+    /// Reset every velocity change to 1, keeping kiai time, volume and the like.
+    Strip,
+    /// Cancel the change of scroll speed by BPM, for games where BPM changes scroll speed,
+    /// e.g. osu!, when the chart comes from a game with constant scroll.
+    Constant,
+    /// Bake the change of scroll speed by BPM into velocity, for games with constant scroll,
+    /// when the chart comes from a game where BPM changes scroll speed.
+    BpmScaled,
+}
+
+/// Normalize scroll velocity against a base BPM.
+pub struct NormalizeScroll {
+    pub mode: ScrollMode,
+    /// The BPM scrolling at the speed of velocity 1. The main BPM is used if `None`.
+    pub base_bpm: Option<f32>,
+}
+
+impl NormalizeScroll {
+    pub fn new(mode: ScrollMode) -> Self {
+        Self {
+            mode,
+            base_bpm: None,
+        }
+    }
+
+    pub fn apply(&self, beatmap: &mut Beatmap) -> io::Result<()> {
+        if self.mode == ScrollMode::Strip {
+            let mut etps = std::mem::take(&mut beatmap.effect_time_points);
+            etps.sort_by_key(|v| v.offset);
+            // Drop the points left with nothing to change.
+            let mut previous = EffectTimePoint::new(i32::MIN, 1.0);
+            for etp in etps {
+                let etp = EffectTimePoint {
+                    velocity_multiplier: 1.0,
+                    ..etp
+                };
+                let unchanged = EffectTimePoint {
+                    offset: etp.offset,
+                    ..previous
+                };
+                if etp != unchanged {
+                    beatmap.effect_time_points.push(etp.clone());
+                }
+                previous = etp;
+            }
+            return Ok(());
+        }
+        let Some(base_bpm) = self.base_bpm.or(beatmap.main_bpm()) else {
+            // Nothing scrolls without BPM.
+            return Ok(());
+        };
+        if !(base_bpm > 0.0 && base_bpm.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Base BPM should be positive.",
+            ));
+        }
+
+        // Every BPM time point resets velocity, so it needs an effect time point to scale.
+        let mut etps = std::mem::take(&mut beatmap.effect_time_points);
+        etps.sort_by_key(|v| v.offset);
+        for btp in &beatmap.bpm_time_points {
+            if etps.iter().any(|v| v.offset == btp.offset) {
+                continue;
+            }
+            let idx = etps.partition_point(|v| v.offset < btp.offset);
+            let etp = match idx.checked_sub(1) {
+                Some(previous) => EffectTimePoint {
+                    offset: btp.offset,
+                    velocity_multiplier: 1.0,
+                    ..etps[previous].clone()
+                },
+                None => EffectTimePoint::new(btp.offset, 1.0),
+            };
+            etps.insert(idx, etp);
+        }
+
+        for etp in &mut etps {
+            let Some(btp) = beatmap.bpm_time_point_at(etp.offset) else {
+                continue;
+            };
+            // Broken BPMs would make the velocity infinite or zero.
+            if !(btp.bpm > 0.0 && btp.bpm.is_finite()) {
+                continue;
+            }
+            etp.velocity_multiplier *= match self.mode {
+                ScrollMode::Constant => base_bpm / btp.bpm,
+                _ => btp.bpm / base_bpm,
+            };
+        }
+        beatmap.effect_time_points = etps;
+        Ok(())
+    }
+}
+
+impl BeatmapTransform for NormalizeScroll {
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>> {
+        self.apply(beatmap)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, Object, TimeSignature};
+
+    #[test]
+    fn normalize_scroll_constant() {
+        let mut beatmap = Beatmap::new();
+        for (offset, bpm) in [(0, 120.0), (4000, 240.0), (5000, 120.0)] {
+            beatmap.bpm_time_points.push(BpmTimePoint {
+                offset,
+                bpm,
+                time_signature: TimeSignature::default(),
+            });
+        }
+        beatmap
+            .effect_time_points
+            .push(EffectTimePoint::new(2000, 0.5));
+        beatmap.objects.push(Object::Note {
+            column: 0,
+            offset: 6000,
+        });
+        assert_eq!(beatmap.main_bpm(), Some(120.0));
+
+        NormalizeScroll::new(ScrollMode::Constant)
+            .apply(&mut beatmap)
+            .unwrap();
+        let velocities = beatmap
+            .effect_time_points
+            .iter()
+            .map(|v| (v.offset, v.velocity_multiplier))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            velocities,
+            vec![(0, 1.0), (2000, 0.5), (4000, 0.5), (5000, 1.0)]
+        );
+    }
+
+    #[test]
+    fn normalize_scroll_zero_bpm() {
+        let mut beatmap = Beatmap::new();
+        for (offset, bpm) in [(0, 120.0), (1000, 0.0)] {
+            beatmap.bpm_time_points.push(BpmTimePoint {
+                offset,
+                bpm,
+                time_signature: TimeSignature::default(),
+            });
+        }
+        NormalizeScroll {
+            base_bpm: Some(120.0),
+            ..NormalizeScroll::new(ScrollMode::Constant)
+        }
+        .apply(&mut beatmap)
+        .unwrap();
+        assert!(beatmap
+            .effect_time_points
+            .iter()
+            .all(|v| v.velocity_multiplier.is_finite()));
+    }
+
+    #[test]
+    fn normalize_scroll_strip() {
+        let mut beatmap = Beatmap::new();
+        beatmap
+            .effect_time_points
+            .push(EffectTimePoint::new(1000, 0.5));
+        beatmap.effect_time_points.push(EffectTimePoint {
+            kiai: true,
+            ..EffectTimePoint::new(2000, 2.0)
+        });
+        beatmap.effect_time_points.push(EffectTimePoint {
+            kiai: true,
+            ..EffectTimePoint::new(3000, 1.5)
+        });
+        beatmap.effect_time_points.push(EffectTimePoint {
+            kiai: true,
+            volume: 50,
+            ..EffectTimePoint::new(4000, 1.0)
+        });
+
+        NormalizeScroll::new(ScrollMode::Strip)
+            .apply(&mut beatmap)
+            .unwrap();
+        let etps = beatmap
+            .effect_time_points
+            .iter()
+            .map(|v| (v.offset, v.velocity_multiplier, v.kiai, v.volume))
+            .collect::<Vec<_>>();
+        assert_eq!(etps, vec![(2000, 1.0, true, 100), (4000, 1.0, true, 50)]);
+    }
+}
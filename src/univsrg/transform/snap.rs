@@ -0,0 +1,250 @@
+use std::io;
+
+use super::super::{
+    traits::BeatmapTransform,
+    types::{Beatmap, Object},
+};
+
+/// 1/1 to 1/16 of a beat, triplets included.
+pub const DEFAULT_DIVISORS: [u32; 8] = [1, 2, 3, 4, 6, 8, 12, 16];
+
+/// The nearest point of the beat grid to `offset`, and the divisor it is on.
+/// Coarser divisors win ties, as long as they come first in `divisors`.
+/// The next BPM time point starts a new grid, so it is a point on every divisor.
+pub fn nearest_snap(beatmap: &Beatmap, offset: i32, divisors: &[u32]) -> Option<(f64, u32)> {
+    let btp = beatmap.bpm_time_point_at(offset)?;
+    let beat_length = btp.beat_length() as f64;
+    let position = (offset - btp.offset) as f64 / beat_length;
+    let first_divisor = *divisors.iter().find(|v| **v > 0)?;
+    let next = beatmap
+        .bpm_time_points
+        .iter()
+        .map(|v| v.offset)
+        .filter(|v| *v > offset)
+        .min()
+        .map(|v| (v as f64, first_divisor));
+    divisors
+        .iter()
+        .filter(|v| **v > 0)
+        .map(|divisor| {
+            let d = *divisor as f64;
+            let time = btp.offset as f64 + (position * d).round() / d * beat_length;
+            (time, *divisor)
+        })
+        .chain(next)
+        .fold(
+            None,
+            |nearest: Option<(f64, u32)>, candidate| match nearest {
+                Some(it) if (it.0 - offset as f64).abs() <= (candidate.0 - offset as f64).abs() => {
+                    Some(it)
+                }
+                _ => Some(candidate),
+            },
+        )
+}
+
+/// Result of [Snap].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapReport {
+    /// The number of objects moved.
+    pub moved: usize,
+    /// Objects too far from any snap, which are left as is.
+    pub off_grid: Vec<Object>,
+    /// Long notes whose head and tail snap to the same point, which become notes.
+    pub collapsed: Vec<Object>,
+}
+
+/// Quantize the offsets and the ends of objects to the beat grid from `bpm_time_points`.
+pub struct Snap {
+    pub divisors: Vec<u32>,
+    /// Maximum distance in ms an offset is moved.
+    pub tolerance: f64,
+}
+
+impl Snap {
+    pub fn new(divisors: Vec<u32>) -> Self {
+        Self {
+            divisors,
+            tolerance: 3.0,
+        }
+    }
+
+    pub fn apply(&self, beatmap: &mut Beatmap) -> io::Result<SnapReport> {
+        if beatmap.bpm_time_points.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No BPM time point.",
+            ));
+        }
+        let snap = |offset: i32| -> Option<i32> {
+            let (time, _) = nearest_snap(beatmap, offset, &self.divisors)?;
+            ((time - offset as f64).abs() <= self.tolerance).then_some(time.round() as i32)
+        };
+
+        let mut report = SnapReport::default();
+        let mut objects = beatmap.objects.clone();
+        for object in &mut objects {
+            let offset = snap(object.offset());
+            let end_offset = object.end_offset().map(snap);
+            if offset.is_none() || end_offset.is_some_and(|v| v.is_none()) {
+                report.off_grid.push(*object);
+            }
+            let snapped = match *object {
+                Object::Note { column, offset: o } => Object::Note {
+                    column,
+                    offset: offset.unwrap_or(o),
+                },
+                Object::LongNote {
+                    column,
+                    offset: o,
+                    end_offset: e,
+                } => {
+                    let offset = offset.unwrap_or(o);
+                    let end_offset = end_offset.flatten().unwrap_or(e);
+                    if end_offset <= offset {
+                        report.collapsed.push(*object);
+                        Object::Note { column, offset }
+                    } else {
+                        Object::LongNote {
+                            column,
+                            offset,
+                            end_offset,
+                        }
+                    }
+                }
+            };
+            if snapped != *object {
+                report.moved += 1;
+                *object = snapped;
+            }
+        }
+        beatmap.objects = objects;
+        Ok(report)
+    }
+}
+
+impl BeatmapTransform for Snap {
+    fn transform_beatmap(&self, beatmap: &mut Beatmap) -> io::Result<Vec<String>> {
+        let report = self.apply(beatmap)?;
+        let off_grid = report.off_grid.iter().map(|v| {
+            format!(
+                "The object at {} ms in column {} is too far from the grid.",
+                v.offset(),
+                v.column()
+            )
+        });
+        let collapsed = report.collapsed.iter().map(|v| {
+            format!(
+                "The long note at {} ms in column {} is too short and becomes a note.",
+                v.offset(),
+                v.column()
+            )
+        });
+        Ok(off_grid.chain(collapsed).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, TimeSignature};
+
+    #[test]
+    fn snap_apply() {
+        let mut beatmap = Beatmap::new();
+        // 1/4 beat is 125 ms, and 1/3 beat is 166.67 ms.
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 100,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 226,
+            },
+            Object::LongNote {
+                column: 1,
+                offset: 265,
+                end_offset: 601,
+            },
+            Object::Note {
+                column: 2,
+                offset: 150,
+            },
+            // Both ends snap to 725 ms.
+            Object::LongNote {
+                column: 3,
+                offset: 724,
+                end_offset: 726,
+            },
+            // Far from the 1/16 grid, but just before the next BPM time point.
+            Object::Note {
+                column: 0,
+                offset: 999,
+            },
+        ];
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 1000,
+            bpm: 150.0,
+            time_signature: TimeSignature::default(),
+        });
+
+        let mut transformed = beatmap.clone();
+        let warnings = Snap::new(DEFAULT_DIVISORS.to_vec())
+            .transform_beatmap(&mut transformed)
+            .unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                "The object at 150 ms in column 2 is too far from the grid.",
+                "The long note at 724 ms in column 3 is too short and becomes a note.",
+            ]
+        );
+        let report = Snap::new(DEFAULT_DIVISORS.to_vec())
+            .apply(&mut beatmap)
+            .unwrap();
+        assert_eq!(report.moved, 4);
+        assert_eq!(
+            beatmap.objects,
+            vec![
+                Object::Note {
+                    column: 0,
+                    offset: 225,
+                },
+                Object::LongNote {
+                    column: 1,
+                    offset: 267,
+                    end_offset: 600,
+                },
+                Object::Note {
+                    column: 2,
+                    offset: 150,
+                },
+                Object::Note {
+                    column: 3,
+                    offset: 725,
+                },
+                Object::Note {
+                    column: 0,
+                    offset: 1000,
+                },
+            ]
+        );
+        assert_eq!(
+            report.off_grid,
+            vec![Object::Note {
+                column: 2,
+                offset: 150,
+            }]
+        );
+        assert_eq!(
+            report.collapsed,
+            vec![Object::LongNote {
+                column: 3,
+                offset: 724,
+                end_offset: 726,
+            }]
+        );
+    }
+}
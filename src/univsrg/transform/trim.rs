@@ -0,0 +1,271 @@
+use std::{collections::HashMap, io};
+
+use super::super::{
+    audio::Audio,
+    resource::ResourceEntry,
+    traits::Transform,
+    types::{Beatmap, BreakPeriod, EffectTimePoint, Object, Package},
+};
+
+/// Make a practice copy of every beatmap with only the objects from `start` to `end` ms,
+/// moved to start at zero, with its audio cut and faded to match.
+///
+/// The copies are appended to the package, and their versions are suffixed with the range,
+/// e.g. "Hard 1m20s-1m45s".
+pub struct Trim {
+    pub start: i32,
+    pub end: i32,
+    /// Duration of the fade-in and the fade-out of the audio in ms.
+    pub fade: i32,
+    /// Replace the beatmaps with the copies instead.
+    pub replace: bool,
+}
+
+fn format_time(ms: i32) -> String {
+    let seconds = ms.max(0) / 1000;
+    format!("{}m{:02}s", seconds / 60, seconds % 60)
+}
+
+impl Trim {
+    pub fn new(start: i32, end: i32) -> Self {
+        Self {
+            start,
+            end,
+            fade: 500,
+            replace: false,
+        }
+    }
+
+    fn suffix(&self) -> String {
+        format!("{}-{}", format_time(self.start), format_time(self.end))
+    }
+
+    /// Cut the objects and the timing of `beatmap`. The audio is left as is.
+    pub fn apply_to_beatmap(&self, beatmap: &Beatmap) -> Beatmap {
+        let (start, end) = (self.start, self.end);
+        let mut trimmed = beatmap.clone();
+
+        trimmed.objects = beatmap
+            .objects
+            .iter()
+            .filter(|v| (start..end).contains(&v.offset()))
+            .map(|v| match *v {
+                Object::LongNote {
+                    column,
+                    offset,
+                    end_offset,
+                } if end_offset > end => Object::LongNote {
+                    column,
+                    offset,
+                    end_offset: end,
+                },
+                v => v,
+            })
+            .filter(|v| v.end_offset().is_none_or(|e| e > v.offset()))
+            .collect();
+
+        // The point in effect at the start is moved to the start,
+        // and BPM time points are moved by whole beats to keep the beat grid.
+        let mut btps = beatmap
+            .bpm_time_points
+            .iter()
+            .filter(|v| v.offset > start && v.offset < end)
+            .cloned()
+            .collect::<Vec<_>>();
+        // Before the first BPM time point, that point itself is kept as is.
+        if let Some(btp) = beatmap
+            .bpm_time_points
+            .iter()
+            .take_while(|v| v.offset <= start)
+            .last()
+        {
+            let beat_length = btp.beat_length() as f64;
+            let beats = ((start - btp.offset) as f64 / beat_length).floor();
+            let mut btp = btp.clone();
+            btp.offset += (beats * beat_length).round() as i32;
+            btps.insert(0, btp);
+        }
+        trimmed.bpm_time_points = btps;
+
+        let mut etps = beatmap
+            .effect_time_points
+            .iter()
+            .filter(|v| v.offset > start && v.offset < end)
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(etp) = beatmap
+            .effect_time_points
+            .iter()
+            .take_while(|v| v.offset <= start)
+            .last()
+        {
+            etps.insert(
+                0,
+                EffectTimePoint {
+                    offset: start,
+                    ..etp.clone()
+                },
+            );
+        }
+        trimmed.effect_time_points = etps;
+
+        trimmed.breaks = beatmap
+            .breaks
+            .iter()
+            .filter(|v| v.end_offset > start && v.offset < end)
+            .map(|v| BreakPeriod {
+                offset: v.offset.max(start),
+                end_offset: v.end_offset.min(end),
+            })
+            .collect();
+
+        trimmed.map_offsets(|v| v - start);
+        trimmed.preview_time = Some(0);
+        trimmed.preview_duration = Some(end - start);
+        trimmed.version = Some(match &beatmap.version {
+            Some(version) => format!("{} {}", version, self.suffix()),
+            None => self.suffix(),
+        });
+        // The copy is not the beatmap online.
+        trimmed.online_ids.clear();
+        // The video and the storyboard would play from the start.
+        trimmed.video = None;
+        trimmed.storyboard = Default::default();
+        trimmed
+    }
+
+    fn trim_audio(&self, entry: &ResourceEntry) -> io::Result<ResourceEntry> {
+        let extension = entry.original_path.extension().and_then(|v| v.to_str());
+        let mut audio =
+            Audio::decode(&entry.bytes, extension)?.slice(self.start as f64, self.end as f64);
+        audio.fade(self.fade as f64, self.fade as f64);
+        let stem = entry
+            .original_path
+            .file_stem()
+            .map(|v| v.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let path = entry
+            .original_path
+            .with_file_name(format!("{} {}.wav", stem, self.suffix()));
+        Ok(ResourceEntry::new(path, audio.encode_wav()?))
+    }
+
+    pub fn apply(&self, package: &mut Package) -> io::Result<()> {
+        if self.start < 0 || self.end <= self.start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The range to trim is empty.",
+            ));
+        }
+        // Beatmaps of a set usually share the audio, so trim it only once.
+        // Trim everything before changing anything, so that an error leaves the package as is.
+        let mut trimmed = HashMap::<ResourceEntry, ResourceEntry>::new();
+        for audio in package.beatmaps.iter().filter_map(|v| v.audio.as_ref()) {
+            if !trimmed.contains_key(audio) {
+                trimmed.insert(audio.clone(), self.trim_audio(audio)?);
+            }
+        }
+        let copies = package
+            .beatmaps
+            .iter()
+            .map(|beatmap| {
+                let mut copy = self.apply_to_beatmap(beatmap);
+                copy.audio = beatmap.audio.as_ref().and_then(|v| trimmed.get(v).cloned());
+                copy
+            })
+            .collect::<Vec<_>>();
+        // The copies leave out the video and the storyboard, which may now be unused.
+        let media = package
+            .beatmaps
+            .iter()
+            .flat_map(|v| v.media_resources().cloned())
+            .collect::<Vec<_>>();
+        match self.replace {
+            true => package.beatmaps = copies,
+            false => package.beatmaps.extend(copies),
+        }
+        package.remove_unreferenced(media);
+        for (original, entry) in trimmed {
+            package.resource_pool.insert(entry);
+            package.remove_unreferenced([original]);
+        }
+        Ok(())
+    }
+}
+
+impl Transform for Trim {
+    fn transform(&self, package: &mut Package) -> io::Result<Vec<String>> {
+        self.apply(package)?;
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, TimeSignature};
+
+    #[test]
+    fn trim_apply_to_beatmap() {
+        let mut beatmap = Beatmap::new();
+        beatmap.version = Some("Hard".to_owned());
+        // A beat is 500 ms.
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 100,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap
+            .effect_time_points
+            .push(EffectTimePoint::new(100, 0.8));
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 1000,
+            },
+            Object::Note {
+                column: 0,
+                offset: 2100,
+            },
+            Object::LongNote {
+                column: 1,
+                offset: 2600,
+                end_offset: 4000,
+            },
+            Object::Note {
+                column: 0,
+                offset: 3500,
+            },
+        ];
+
+        let trimmed = Trim::new(2000, 3000).apply_to_beatmap(&beatmap);
+        assert_eq!(trimmed.version.as_deref(), Some("Hard 0m02s-0m03s"));
+        assert_eq!(
+            trimmed.objects,
+            vec![
+                Object::Note {
+                    column: 0,
+                    offset: 100,
+                },
+                Object::LongNote {
+                    column: 1,
+                    offset: 600,
+                    end_offset: 1000,
+                },
+            ]
+        );
+        assert_eq!(trimmed.bpm_time_points[0].offset, -400);
+        assert_eq!(trimmed.effect_time_points[0].offset, 0);
+        assert_eq!(trimmed.effect_time_points[0].velocity_multiplier, 0.8);
+        assert_eq!(trimmed.preview_time, Some(0));
+
+        // The range starts before the first BPM time point.
+        let trimmed = Trim::new(0, 3000).apply_to_beatmap(&beatmap);
+        let offsets = trimmed
+            .bpm_time_points
+            .iter()
+            .map(|v| v.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![100]);
+    }
+}
@@ -1,8 +1,52 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
 // Note: 使用 super 表示上一级模块，即 univsrg。
 // Note: mod.rs 已经将所有模块引入，所以不需再引入，只需用 use 语句缩写。
-use super::resource::{ResourceEntry, ResourcePool};
+use super::{
+    judgement::HitWindows,
+    resource::{ResourceEntry, ResourcePool},
+};
+
+/// VSRGs that univsrg knows about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Game {
+    Osu,
+    Malody,
+    Quaver,
+    Etterna,
+    StepMania,
+    Bms,
+}
+
+impl Game {
+    pub const ALL: [Game; 6] = [
+        Game::Osu,
+        Game::Malody,
+        Game::Quaver,
+        Game::Etterna,
+        Game::StepMania,
+        Game::Bms,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Game::Osu => "osu",
+            Game::Malody => "malody",
+            Game::Quaver => "quaver",
+            Game::Etterna => "etterna",
+            Game::StepMania => "stepmania",
+            Game::Bms => "bms",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.name() == name)
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct LatinAndUnicodeString {
     pub latin: Option<String>,
     pub unicode: Option<String>,
@@ -26,20 +70,290 @@ impl LatinAndUnicodeString {
     }
 }
 
-#[derive(Debug)]
+/// Time signature of a bar, e.g. 7/8.
+///
+/// BMS measure lengths (e.g. 0.75) are stored as the equivalent fraction of a whole note (3/4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    pub fn new(numerator: u32, denominator: u32) -> io::Result<Self> {
+        if denominator == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Time signature {}/0 has a zero denominator.", numerator),
+            ));
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+
+    /// Approximate a measure length in whole notes, with the denominator limited to 64.
+    pub fn from_measure_length(measure_length: f32) -> io::Result<Self> {
+        if !(measure_length.is_finite() && measure_length > 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Measure length {} is not positive.", measure_length),
+            ));
+        }
+        let mut best = Self::new(1, 1)?;
+        let mut best_error = f32::INFINITY;
+        for denominator in [1, 2, 4, 8, 16, 32, 64, 3, 6, 12, 24, 48] {
+            let numerator = (measure_length * denominator as f32).round().max(1.0) as u32;
+            let error = (numerator as f32 / denominator as f32 - measure_length).abs();
+            if error < best_error - f32::EPSILON {
+                best = Self::new(numerator, denominator)?;
+                best_error = error;
+            }
+        }
+        Ok(best)
+    }
+
+    /// Length of a bar in whole notes.
+    pub fn measure_length(&self) -> f32 {
+        self.numerator as f32 / self.denominator as f32
+    }
+    /// Length of a bar in quarter-note beats, which is the unit of [BpmTimePoint::bpm].
+    pub fn beats_per_bar(&self) -> f32 {
+        self.measure_length() * 4.0
+    }
+    /// The closest whole number of quarter-note beats, for formats that only have a meter.
+    pub fn closest_meter(&self) -> u32 {
+        (self.beats_per_bar().round() as u32).max(1)
+    }
+    /// Whether [TimeSignature::closest_meter] represents this time signature exactly.
+    pub fn is_whole_meter(&self) -> bool {
+        self.closest_meter() as u64 * self.denominator as u64 == self.numerator as u64 * 4
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BpmTimePoint {
     pub offset: i32,
+    /// Quarter-note beats per minute.
     pub bpm: f32,
-    pub beats_per_bar: u32,
+    pub time_signature: TimeSignature,
 }
 
-#[derive(Debug)]
+impl BpmTimePoint {
+    /// Milliseconds per quarter-note beat.
+    pub fn beat_length(&self) -> f32 {
+        60000.0 / self.bpm
+    }
+}
+
+/// Sample set of hit sounds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SampleSet {
+    /// The default sample set of the beatmap.
+    #[default]
+    Default,
+    Normal,
+    Soft,
+    Drum,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EffectTimePoint {
     pub offset: i32,
     pub velocity_multiplier: f32,
+    /// Highlighted section, a.k.a. kiai time.
+    pub kiai: bool,
+    /// Hide the bar line at `offset`.
+    pub omit_first_barline: bool,
+    /// Volume of hit sounds in percent.
+    pub volume: u8,
+    pub sample_set: SampleSet,
+    /// Index of custom hit sounds, 0 for the default ones.
+    pub sample_index: u32,
+}
+
+impl EffectTimePoint {
+    pub fn new(offset: i32, velocity_multiplier: f32) -> Self {
+        Self {
+            offset,
+            velocity_multiplier,
+            kiai: false,
+            omit_first_barline: false,
+            volume: 100,
+            sample_set: SampleSet::Default,
+            sample_index: 0,
+        }
+    }
+}
+
+/// IDs of a beatmap on the website of a game.
+///
+/// e.g. beatmap ID and beatmap set ID on osu!, chart ID and song ID on Malody,
+/// map ID and map set ID on Quaver.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OnlineId {
+    pub beatmap_id: Option<i64>,
+    pub beatmap_set_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnRole {
+    Key,
+    Scratch,
+    Pedal,
+}
+
+/// The hand or player side a column belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub role: ColumnRole,
+    pub side: Option<Side>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutPreset {
+    /// Keys only.
+    Keys,
+    /// A scratch at the left, e.g. BMS 7K+1 and osu!mania SpecialStyle.
+    ScratchLeft,
+    /// A scratch at the right, e.g. BMS 2P side.
+    ScratchRight,
+    /// A scratch at each side, e.g. BMS double play.
+    DoubleScratch,
+    /// Roles are given by each column.
+    Custom,
 }
 
-#[derive(Debug)]
+impl LayoutPreset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayoutPreset::Keys => "keys",
+            LayoutPreset::ScratchLeft => "scratch_left",
+            LayoutPreset::ScratchRight => "scratch_right",
+            LayoutPreset::DoubleScratch => "double_scratch",
+            LayoutPreset::Custom => "custom",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            LayoutPreset::Keys,
+            LayoutPreset::ScratchLeft,
+            LayoutPreset::ScratchRight,
+            LayoutPreset::DoubleScratch,
+            LayoutPreset::Custom,
+        ]
+        .into_iter()
+        .find(|it| it.name() == name)
+    }
+}
+
+/// Roles of the columns of a beatmap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub preset: LayoutPreset,
+    pub columns: Vec<Column>,
+}
+
+impl Layout {
+    /// Make the columns of `preset`.
+    /// Columns in the left half are on the left side, and so on. A middle column has no side.
+    /// [LayoutPreset::Custom] makes keys only.
+    pub fn new(preset: LayoutPreset, column_count: u32) -> Self {
+        let columns = (0..column_count)
+            .map(|idx| {
+                let is_last = idx + 1 == column_count;
+                let role = match preset {
+                    LayoutPreset::ScratchLeft | LayoutPreset::DoubleScratch if idx == 0 => {
+                        ColumnRole::Scratch
+                    }
+                    LayoutPreset::ScratchRight | LayoutPreset::DoubleScratch if is_last => {
+                        ColumnRole::Scratch
+                    }
+                    _ => ColumnRole::Key,
+                };
+                let side = if idx * 2 + 1 < column_count {
+                    Some(Side::Left)
+                } else if idx * 2 + 1 > column_count {
+                    Some(Side::Right)
+                } else {
+                    None
+                };
+                Column { role, side }
+            })
+            .collect();
+        Self { preset, columns }
+    }
+
+    /// Make a layout from the columns, with the preset that makes the same columns if any.
+    pub fn from_columns(columns: Vec<Column>) -> Self {
+        let column_count = columns.len() as u32;
+        let preset = [
+            LayoutPreset::Keys,
+            LayoutPreset::ScratchLeft,
+            LayoutPreset::ScratchRight,
+            LayoutPreset::DoubleScratch,
+        ]
+        .into_iter()
+        .find(|v| Self::new(*v, column_count).columns == columns)
+        .unwrap_or(LayoutPreset::Custom);
+        Self { preset, columns }
+    }
+
+    pub fn is_scratch(&self, column: u32) -> bool {
+        self.columns
+            .get(column as usize)
+            .is_some_and(|v| v.role == ColumnRole::Scratch)
+    }
+}
+
+/// A video played instead of the background.
+#[derive(Debug, Clone)]
+pub struct Video {
+    pub resource: ResourceEntry,
+    /// When the video starts, relative to the audio.
+    pub offset: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakPeriod {
+    pub offset: i32,
+    pub end_offset: i32,
+}
+
+/// Storyboards are kept in the osu! scripting language, since no other VSRG supports them.
+#[derive(Debug, Clone, Default)]
+pub struct Storyboard {
+    /// The `.osb` file shared by all difficulties of a beatmap set.
+    pub script: Option<ResourceEntry>,
+    /// Storyboard events in the difficulty, one event with its commands per item.
+    pub events: Vec<String>,
+    /// Images and samples used by `script` and `events`.
+    pub resources: Vec<ResourceEntry>,
+}
+
+impl Storyboard {
+    pub fn is_empty(&self) -> bool {
+        self.script.is_none() && self.events.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Object {
     Note {
         column: u32,
@@ -52,24 +366,80 @@ pub enum Object {
     },
 }
 
+impl Object {
+    pub fn column(&self) -> u32 {
+        match self {
+            Object::Note { column, .. } | Object::LongNote { column, .. } => *column,
+        }
+    }
+    pub fn offset(&self) -> i32 {
+        match self {
+            Object::Note { offset, .. } | Object::LongNote { offset, .. } => *offset,
+        }
+    }
+    pub fn end_offset(&self) -> Option<i32> {
+        match self {
+            Object::Note { .. } => None,
+            Object::LongNote { end_offset, .. } => Some(*end_offset),
+        }
+    }
+    /// Map the start and the end of the object.
+    pub fn map_offsets(&self, f: impl Fn(i32) -> i32) -> Self {
+        match *self {
+            Object::Note { column, offset } => Object::Note {
+                column,
+                offset: f(offset),
+            },
+            Object::LongNote {
+                column,
+                offset,
+                end_offset,
+            } => Object::LongNote {
+                column,
+                offset: f(offset),
+                end_offset: f(end_offset),
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Beatmap {
     pub title: LatinAndUnicodeString,
     pub artist: LatinAndUnicodeString,
     pub version: Option<String>,
     pub creator: Option<String>,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+    pub online_ids: HashMap<Game, OnlineId>,
+    pub genre: Option<String>,
+    pub language: Option<String>,
+    /// Fields of the original format without a counterpart in univsrg.
+    pub custom_fields: BTreeMap<String, String>,
     pub column_count: Option<u32>,
+    /// Roles of the columns. All columns are keys if it is None.
+    pub layout: Option<Layout>,
     pub audio: Option<ResourceEntry>,
     pub audio_lead_in: Option<i32>,
     pub preview_time: Option<i32>,
+    /// Length of the preview in milliseconds.
+    pub preview_duration: Option<i32>,
     pub background: Option<ResourceEntry>,
+    pub video: Option<Video>,
+    pub storyboard: Storyboard,
+    /// HP drain rate of osu!.
     pub hp_difficulty: Option<f32>,
+    /// Overall difficulty of osu!.
     pub acc_difficulty: Option<f32>,
+    pub hit_windows: Option<HitWindows>,
 
     pub bpm_time_points: Vec<BpmTimePoint>,
     pub effect_time_points: Vec<EffectTimePoint>,
+    pub breaks: Vec<BreakPeriod>,
     pub objects: Vec<Object>,
 }
 
+#[derive(Default)]
 pub struct Package {
     pub beatmaps: Vec<Beatmap>,
     pub resource_pool: ResourcePool,
@@ -82,32 +452,164 @@ impl Beatmap {
             artist: LatinAndUnicodeString::new(),
             version: None,
             creator: None,
+            source: None,
+            tags: vec![],
+            online_ids: HashMap::new(),
+            genre: None,
+            language: None,
+            custom_fields: BTreeMap::new(),
             column_count: None,
+            layout: None,
             audio: None,
             audio_lead_in: None,
             preview_time: None,
+            preview_duration: None,
             background: None,
+            video: None,
+            storyboard: Storyboard::default(),
             hp_difficulty: None,
             acc_difficulty: None,
+            hit_windows: None,
             bpm_time_points: vec![],
             effect_time_points: vec![],
+            breaks: vec![],
             objects: vec![],
         }
     }
+    /// Map every point in time, i.e. objects, time points, breaks, the preview time and the video.
+    /// Durations such as `audio_lead_in` are left as is.
+    pub fn map_offsets(&mut self, f: impl Fn(i32) -> i32) {
+        for object in &mut self.objects {
+            *object = object.map_offsets(&f);
+        }
+        for btp in &mut self.bpm_time_points {
+            btp.offset = f(btp.offset);
+        }
+        for etp in &mut self.effect_time_points {
+            etp.offset = f(etp.offset);
+        }
+        for b in &mut self.breaks {
+            b.offset = f(b.offset);
+            b.end_offset = f(b.end_offset);
+        }
+        self.preview_time = self.preview_time.map(&f);
+        if let Some(video) = &mut self.video {
+            video.offset = f(video.offset);
+        }
+    }
+
+    /// The BPM time point in effect at `offset`.
+    /// Offsets before the first point use the first point.
+    pub fn bpm_time_point_at(&self, offset: i32) -> Option<&BpmTimePoint> {
+        self.bpm_time_points
+            .iter()
+            .take_while(|v| v.offset <= offset)
+            .last()
+            .or(self.bpm_time_points.first())
+    }
+
+    /// The start of every bar until `end`, following the BPM time points.
+    /// Bars shorter than 1 ms are skipped, since they would only fill the list.
+    pub fn measure_offsets(&self, end: i32) -> Vec<i32> {
+        let mut offsets = vec![];
+        for (idx, btp) in self.bpm_time_points.iter().enumerate() {
+            let next = self
+                .bpm_time_points
+                .get(idx + 1)
+                .map_or(end, |v| v.offset.min(end));
+            let measure = btp.beat_length() as f64 * btp.time_signature.beats_per_bar() as f64;
+            if measure.is_nan() || measure < 1.0 {
+                continue;
+            }
+            let mut time = btp.offset as f64;
+            while time < next as f64 {
+                offsets.push(time.round() as i32);
+                time += measure;
+            }
+        }
+        offsets
+    }
+
+    /// The column count, checked to be positive and to hold every object.
+    pub fn checked_column_count(&self) -> io::Result<u32> {
+        let column_count = self.column_count.ok_or(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "[beatmap.column_count] is None",
+        ))?;
+        if column_count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "[beatmap.column_count] is 0",
+            ));
+        }
+        if let Some(object) = self.objects.iter().find(|v| v.column() >= column_count) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The object at {} ms is in column {}, out of {} columns.",
+                    object.offset(),
+                    object.column(),
+                    column_count
+                ),
+            ));
+        }
+        Ok(column_count)
+    }
+    /// The time the last object ends.
+    pub fn end_offset(&self) -> Option<i32> {
+        self.objects
+            .iter()
+            .map(|v| v.end_offset().unwrap_or(v.offset()))
+            .max()
+    }
+    /// The BPM lasting longest until the last object ends.
+    pub fn main_bpm(&self) -> Option<f32> {
+        let end = self.end_offset().unwrap_or(i32::MIN);
+        let mut durations = Vec::<(f32, i64)>::new();
+        for (idx, btp) in self.bpm_time_points.iter().enumerate() {
+            let next = self
+                .bpm_time_points
+                .get(idx + 1)
+                .map_or(end, |v| v.offset.min(end));
+            let duration = (next as i64 - btp.offset as i64).max(0);
+            match durations.iter_mut().find(|v| v.0 == btp.bpm) {
+                Some(it) => it.1 += duration,
+                None => durations.push((btp.bpm, duration)),
+            }
+        }
+        // The first one wins a tie.
+        durations.iter().rev().max_by_key(|v| v.1).map(|v| v.0)
+    }
+
     pub fn make_basename(&self) -> String {
         let mut names = Vec::<&str>::new();
-        self.creator.as_ref().map(|it| {
+        if let Some(it) = &self.creator {
             names.push(it);
-        });
-        self.title.unicode_or_latin().map(|it| {
+        }
+        if let Some(it) = self.title.unicode_or_latin() {
             names.push(it);
-        });
-        self.version.as_ref().map(|it| {
+        }
+        if let Some(it) = &self.version {
             names.push(it);
-        });
+        }
         // Note: 不能对 Vec::<&String> 进行 join。因为 &String 没有提供 iter 方法。
         names.join(" - ")
     }
+
+    /// Resources used by the video and the storyboard.
+    pub fn media_resources(&self) -> impl Iterator<Item = &ResourceEntry> {
+        self.video
+            .iter()
+            .map(|v| &v.resource)
+            .chain(self.storyboard.script.iter())
+            .chain(self.storyboard.resources.iter())
+    }
+}
+
+impl Default for Beatmap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Package {
@@ -117,4 +619,97 @@ impl Package {
             resource_pool: ResourcePool::new(),
         }
     }
+
+    /// Remove `entries` from the resource pool unless a beatmap still uses them.
+    pub fn remove_unreferenced(&mut self, entries: impl IntoIterator<Item = ResourceEntry>) {
+        for entry in entries {
+            if !self.is_referenced(&entry) {
+                self.resource_pool.remove(&entry);
+            }
+        }
+    }
+
+    /// Whether any beatmap uses `entry`.
+    pub fn is_referenced(&self, entry: &ResourceEntry) -> bool {
+        self.beatmaps.iter().any(|beatmap| {
+            beatmap.audio.as_ref() == Some(entry)
+                || beatmap.background.as_ref() == Some(entry)
+                || beatmap.video.as_ref().map(|v| &v.resource) == Some(entry)
+                || beatmap.storyboard.script.as_ref() == Some(entry)
+                || beatmap.storyboard.resources.contains(entry)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn time_signature_from_measure_length() {
+        assert_eq!(
+            TimeSignature::from_measure_length(1.0).unwrap(),
+            TimeSignature::new(1, 1).unwrap()
+        );
+        assert_eq!(
+            TimeSignature::from_measure_length(0.75).unwrap(),
+            TimeSignature::new(3, 4).unwrap()
+        );
+        assert_eq!(
+            TimeSignature::from_measure_length(0.875).unwrap(),
+            TimeSignature::new(7, 8).unwrap()
+        );
+        assert_eq!(
+            TimeSignature::from_measure_length(1.0 / 3.0).unwrap(),
+            TimeSignature::new(1, 3).unwrap()
+        );
+        assert!(TimeSignature::from_measure_length(0.0).is_err());
+        assert!(TimeSignature::from_measure_length(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn layout_new() {
+        let layout = Layout::new(LayoutPreset::ScratchLeft, 8);
+        assert!(layout.is_scratch(0));
+        assert!(!layout.is_scratch(1));
+        assert_eq!(layout.columns[0].side, Some(Side::Left));
+        assert_eq!(layout.columns[7].side, Some(Side::Right));
+
+        let layout = Layout::new(LayoutPreset::DoubleScratch, 16);
+        assert!(layout.is_scratch(0));
+        assert!(layout.is_scratch(15));
+
+        let layout = Layout::new(LayoutPreset::Keys, 7);
+        assert_eq!(layout.columns[3].side, None);
+        assert!((0..7).all(|v| !layout.is_scratch(v)));
+    }
+
+    #[test]
+    fn time_signature_closest_meter() {
+        assert_eq!(TimeSignature::new(4, 4).unwrap().closest_meter(), 4);
+        assert!(TimeSignature::new(4, 4).unwrap().is_whole_meter());
+        assert!(TimeSignature::new(6, 8).unwrap().is_whole_meter());
+        assert_eq!(TimeSignature::new(7, 8).unwrap().closest_meter(), 4);
+        assert!(!TimeSignature::new(7, 8).unwrap().is_whole_meter());
+        assert_eq!(TimeSignature::new(1, 16).unwrap().closest_meter(), 1);
+        assert!(TimeSignature::new(4, 0).is_err());
+        // No overflow for huge signatures.
+        assert!(!TimeSignature::new(u32::MAX, u32::MAX - 1)
+            .unwrap()
+            .is_whole_meter());
+    }
+
+    #[test]
+    fn measure_offsets() {
+        let mut beatmap = Beatmap::new();
+        for (offset, bpm) in [(0, 120.0), (4000, 6e9), (5000, 60.0)] {
+            beatmap.bpm_time_points.push(BpmTimePoint {
+                offset,
+                bpm,
+                time_signature: TimeSignature::default(),
+            });
+        }
+        // The bars of 0.04 µs are skipped instead of filling the list.
+        assert_eq!(beatmap.measure_offsets(10000), vec![0, 2000, 5000, 9000]);
+    }
 }
@@ -0,0 +1,396 @@
+//! Checks for mistakes in beatmaps that games would reject or play wrong.
+
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use super::{
+    audio::Audio,
+    osu::storyboard::referenced_paths,
+    resource::{ResourceEntry, ResourcePool},
+    types::{Beatmap, Package},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Probably a mistake, but the beatmap can be played.
+    Warning,
+    /// The beatmap is broken.
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Where the problem is in ms, if it is at some point.
+    pub offset: Option<i32>,
+    pub column: Option<u32>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            offset: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+    fn at(mut self, offset: i32) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+    fn in_column(mut self, column: u32) -> Self {
+        self.column = Some(column);
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning")?,
+            Severity::Error => write!(f, "error")?,
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at {} ms", offset)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, " in column {}", column)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+fn check_resources(
+    beatmap: &Beatmap,
+    resource_pool: &ResourcePool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut resources = Vec::<(&str, &ResourceEntry)>::new();
+    match &beatmap.audio {
+        Some(audio) => resources.push(("audio", audio)),
+        None => diagnostics.push(Diagnostic::new(Severity::Error, "There is no audio.")),
+    }
+    if let Some(background) = &beatmap.background {
+        resources.push(("background", background));
+    }
+    if let Some(video) = &beatmap.video {
+        resources.push(("video", &video.resource));
+    }
+    if let Some(script) = &beatmap.storyboard.script {
+        resources.push(("storyboard", script));
+    }
+    for resource in &beatmap.storyboard.resources {
+        resources.push(("storyboard file", resource));
+    }
+    for (kind, resource) in resources {
+        if !resource_pool.contains(resource) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                format!(
+                    "The {} {} is not in the package.",
+                    kind,
+                    resource.original_path.display()
+                ),
+            ));
+        }
+    }
+
+    // Files the storyboard uses but are not found when it was loaded.
+    let mut script = beatmap.storyboard.events.join("\n");
+    if let Some(entry) = &beatmap.storyboard.script {
+        script = String::from_utf8_lossy(&entry.bytes).into_owned() + "\n" + &script;
+    }
+    let loaded = beatmap
+        .storyboard
+        .resources
+        .iter()
+        .map(|v| v.original_path.clone())
+        .collect::<Vec<PathBuf>>();
+    for path in referenced_paths(&script) {
+        if !loaded.contains(&path) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("The storyboard file {} is missing.", path.display()),
+            ));
+        }
+    }
+}
+
+fn check_timing(beatmap: &Beatmap, diagnostics: &mut Vec<Diagnostic>) {
+    if beatmap.bpm_time_points.is_empty() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "There is no BPM time point.",
+        ));
+    }
+    for btp in &beatmap.bpm_time_points {
+        if !(btp.bpm > 0.0 && btp.bpm.is_finite()) {
+            diagnostics.push(
+                Diagnostic::new(Severity::Error, format!("BPM {} is not positive.", btp.bpm))
+                    .at(btp.offset),
+            );
+        }
+    }
+    let offsets = [
+        (
+            "BPM",
+            beatmap
+                .bpm_time_points
+                .iter()
+                .map(|v| v.offset)
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "effect",
+            beatmap
+                .effect_time_points
+                .iter()
+                .map(|v| v.offset)
+                .collect::<Vec<_>>(),
+        ),
+    ];
+    for (kind, offsets) in offsets {
+        for pair in offsets.windows(2) {
+            if pair[1] < pair[0] {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Warning,
+                        format!("The {} time point is earlier than the previous one.", kind),
+                    )
+                    .at(pair[1]),
+                );
+            } else if pair[1] == pair[0] {
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Warning,
+                        format!("There are more than one {} time points here.", kind),
+                    )
+                    .at(pair[1]),
+                );
+            }
+        }
+    }
+}
+
+fn check_objects(
+    beatmap: &Beatmap,
+    audio_duration: Option<f64>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(column_count) = beatmap.column_count else {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            "The column count is unknown.",
+        ));
+        return;
+    };
+
+    let mut objects = beatmap.objects.clone();
+    objects.sort_by_key(|v| (v.column(), v.offset()));
+    // The previous object, and the latest end of the long notes so far in its column.
+    let mut previous = None;
+    let mut held_until = None;
+    for object in &objects {
+        let column = object.column();
+        let offset = object.offset();
+        let diagnostic = |severity, message: &str| {
+            Diagnostic::new(severity, message)
+                .at(offset)
+                .in_column(column)
+        };
+        if column >= column_count {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                &format!("The column is out of {} columns.", column_count),
+            ));
+        }
+        if let Some(end_offset) = object.end_offset() {
+            if end_offset <= offset {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    "The long note ends before it starts.",
+                ));
+            }
+        }
+        if previous.is_some_and(|v: (u32, i32)| v.0 != column) {
+            held_until = None;
+        }
+        if previous == Some((column, offset)) {
+            diagnostics.push(diagnostic(Severity::Error, "Notes are stacked."));
+        } else if held_until.is_some_and(|v| offset <= v) {
+            diagnostics.push(diagnostic(
+                Severity::Error,
+                "The note is inside a long note.",
+            ));
+        }
+        if let Some(audio_duration) = audio_duration {
+            if object.end_offset().unwrap_or(offset) as f64 > audio_duration {
+                diagnostics.push(diagnostic(
+                    Severity::Warning,
+                    "The object is after the audio ends.",
+                ));
+            }
+        }
+        previous = Some((column, offset));
+        held_until = held_until.max(object.end_offset());
+    }
+}
+
+fn validate_with_audio_duration(
+    beatmap: &Beatmap,
+    resource_pool: &ResourcePool,
+    audio_duration: Option<f64>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    check_resources(beatmap, resource_pool, &mut diagnostics);
+    check_timing(beatmap, &mut diagnostics);
+    check_objects(beatmap, audio_duration, &mut diagnostics);
+    diagnostics.sort_by_key(|v| (v.offset, v.column));
+    diagnostics
+}
+
+fn audio_duration(entry: &ResourceEntry, diagnostics: &mut Vec<Diagnostic>) -> Option<f64> {
+    let extension = entry.original_path.extension().and_then(|v| v.to_str());
+    match Audio::decode(&entry.bytes, extension) {
+        Ok(audio) => Some(audio.duration_ms()),
+        Err(e) => {
+            diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                format!("The audio cannot be decoded: {}", e),
+            ));
+            None
+        }
+    }
+}
+
+/// Check a beatmap, with its resources in `resource_pool`.
+pub fn validate_beatmap(beatmap: &Beatmap, resource_pool: &ResourcePool) -> Vec<Diagnostic> {
+    let mut decode_diagnostics = vec![];
+    let duration = beatmap
+        .audio
+        .as_ref()
+        .and_then(|v| audio_duration(v, &mut decode_diagnostics));
+    let mut diagnostics = validate_with_audio_duration(beatmap, resource_pool, duration);
+    diagnostics.extend(decode_diagnostics);
+    diagnostics
+}
+
+/// Check every beatmap of a package. Diagnostics are in the order of the beatmaps.
+pub fn validate(package: &Package) -> Vec<Vec<Diagnostic>> {
+    // Beatmaps of a set usually share the audio, so decode it only once.
+    let mut durations = HashMap::<ResourceEntry, (Option<f64>, Vec<Diagnostic>)>::new();
+    package
+        .beatmaps
+        .iter()
+        .map(|beatmap| {
+            let (duration, decode_diagnostics) = match &beatmap.audio {
+                Some(audio) => durations
+                    .entry(audio.clone())
+                    .or_insert_with(|| {
+                        let mut diagnostics = vec![];
+                        (audio_duration(audio, &mut diagnostics), diagnostics)
+                    })
+                    .clone(),
+                None => (None, vec![]),
+            };
+            let mut diagnostics =
+                validate_with_audio_duration(beatmap, &package.resource_pool, duration);
+            diagnostics.extend(decode_diagnostics);
+            diagnostics
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, EffectTimePoint, Object, TimeSignature};
+
+    #[test]
+    fn validate_beatmap_diagnostics() {
+        let audio = Audio {
+            sample_rate: 1000,
+            channel_count: 1,
+            samples: vec![0.0; 5000],
+        };
+        let entry = ResourceEntry::new("audio.wav".into(), audio.encode_wav().unwrap());
+        let mut resource_pool = ResourcePool::new();
+        resource_pool.insert(entry.clone());
+
+        let mut beatmap = Beatmap::new();
+        beatmap.audio = Some(entry);
+        beatmap.column_count = Some(4);
+        for (offset, bpm) in [(0, 120.0), (0, -1.0)] {
+            beatmap.bpm_time_points.push(BpmTimePoint {
+                offset,
+                bpm,
+                time_signature: TimeSignature::default(),
+            });
+        }
+        beatmap.effect_time_points = vec![
+            EffectTimePoint::new(1000, 1.0),
+            EffectTimePoint::new(500, 1.0),
+        ];
+        beatmap.objects = vec![
+            Object::LongNote {
+                column: 0,
+                offset: 1000,
+                end_offset: 3000,
+            },
+            Object::Note {
+                column: 0,
+                offset: 1500,
+            },
+            Object::Note {
+                column: 0,
+                offset: 2000,
+            },
+            Object::Note {
+                column: 1,
+                offset: 1000,
+            },
+            Object::Note {
+                column: 1,
+                offset: 1000,
+            },
+            Object::LongNote {
+                column: 2,
+                offset: 1000,
+                end_offset: 1000,
+            },
+            Object::Note {
+                column: 4,
+                offset: 1000,
+            },
+            Object::Note {
+                column: 3,
+                offset: 6000,
+            },
+        ];
+
+        let diagnostics = validate_beatmap(&beatmap, &resource_pool);
+        let found = diagnostics
+            .iter()
+            .map(|v| (v.severity, v.offset, v.column))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (Severity::Error, Some(0), None),
+                (Severity::Warning, Some(0), None),
+                (Severity::Warning, Some(500), None),
+                (Severity::Error, Some(1000), Some(1)),
+                (Severity::Error, Some(1000), Some(2)),
+                (Severity::Error, Some(1000), Some(4)),
+                (Severity::Error, Some(1500), Some(0)),
+                (Severity::Error, Some(2000), Some(0)),
+                (Severity::Warning, Some(6000), Some(3)),
+            ]
+        );
+        assert_eq!(
+            diagnostics[6].to_string(),
+            "error at 1500 ms in column 0: The note is inside a long note."
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Showing beatmaps scrolling in a terminal.
+
+use std::{
+    io::{self, Write},
+    panic::{self, PanicHookInfo},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+
+use super::types::{Beatmap, Package};
+
+const NOTE: &str = "▆▆▆";
+const LONG_NOTE_BODY: &str = " █ ";
+const MEASURE_LINE: &str = "───";
+const EMPTY: &str = "   ";
+/// Rows below the judgement line.
+const BOTTOM_ROWS: i32 = 2;
+const MIN_MS_PER_ROW: i32 = 1;
+const MAX_MS_PER_ROW: i32 = 1000;
+
+const HELP: &str =
+    "q: quit, space: play, ↑↓: seek, PgUp/PgDn: page, Home/End, +/-: zoom, ←→/Tab: difficulty";
+
+type PanicHook = dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static;
+
+fn restore_terminal() {
+    // Nothing is left to report errors to.
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+/// Restores the terminal when dropped, even when the viewer panics.
+struct TerminalGuard {
+    previous_hook: Arc<PanicHook>,
+}
+
+impl TerminalGuard {
+    fn new(out: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        // Restore the terminal before the panic message is printed, or it is lost with the
+        // alternate screen.
+        let previous_hook: Arc<PanicHook> = Arc::from(panic::take_hook());
+        let hook = previous_hook.clone();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            hook(info);
+        }));
+        let guard = Self { previous_hook };
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+        // Hooks can't be changed while panicking.
+        if !thread::panicking() {
+            let hook = self.previous_hook.clone();
+            panic::set_hook(Box::new(move |info| hook(info)));
+        }
+    }
+}
+
+/// A terminal viewer of the beatmaps of a package.
+pub struct Viewer<'a> {
+    package: &'a Package,
+    /// Index of the beatmap shown.
+    pub index: usize,
+    /// Time at the judgement line in ms.
+    pub time: i32,
+    /// Time of every row in ms.
+    pub ms_per_row: i32,
+    pub playing: bool,
+}
+
+impl<'a> Viewer<'a> {
+    pub fn new(package: &'a Package) -> Self {
+        let mut viewer = Self {
+            package,
+            index: 0,
+            time: 0,
+            ms_per_row: 20,
+            playing: false,
+        };
+        viewer.seek_to_start();
+        viewer
+    }
+
+    fn beatmap(&self) -> Option<&'a Beatmap> {
+        self.package.beatmaps.get(self.index)
+    }
+
+    fn seek_to_start(&mut self) {
+        let first = self
+            .beatmap()
+            .and_then(|v| v.objects.iter().map(|v| v.offset()).min());
+        self.time = first.unwrap_or(0).min(0);
+    }
+
+    fn seek_to_end(&mut self) {
+        self.time = self.beatmap().and_then(|v| v.end_offset()).unwrap_or(0);
+    }
+
+    /// Lines of the screen from the top, without a trailing newline.
+    pub fn frame(&self, width: u16, height: u16) -> Vec<String> {
+        let (width, height) = (width as usize, height as i32);
+        let Some(beatmap) = self.beatmap() else {
+            return vec!["No beatmap.".to_owned()];
+        };
+        let column_count = beatmap.column_count.unwrap_or(0) as usize;
+        let mut lines = vec![
+            format!(
+                "[{}/{}] {}",
+                self.index + 1,
+                self.package.beatmaps.len(),
+                beatmap.make_basename()
+            ),
+            format!(
+                "{}:{:02}.{:03}  {} ms/row{}",
+                self.time.max(0) / 60000,
+                self.time.max(0) / 1000 % 60,
+                self.time.max(0) % 1000,
+                self.ms_per_row,
+                if self.playing { "  playing" } else { "" }
+            ),
+        ];
+        // Rows from the top of the playfield to the bottom.
+        let row_count = (height - lines.len() as i32 - 1).max(BOTTOM_ROWS + 1);
+        let row_time = |row: i32| self.time + (row_count - 1 - BOTTOM_ROWS - row) * self.ms_per_row;
+        let top = row_time(0) + self.ms_per_row;
+        let measures = beatmap.measure_offsets(top);
+
+        for row in 0..row_count {
+            let start = row_time(row);
+            let end = start + self.ms_per_row;
+            let in_row = |offset: i32| start <= offset && offset < end;
+            let mut cells = vec![EMPTY; column_count];
+            if measures.iter().any(|v| in_row(*v)) {
+                cells.fill(MEASURE_LINE);
+            }
+            for object in &beatmap.objects {
+                let Some(cell) = cells.get_mut(object.column() as usize) else {
+                    continue;
+                };
+                if in_row(object.offset()) {
+                    *cell = NOTE;
+                } else if object
+                    .end_offset()
+                    .is_some_and(|v| object.offset() < start && v >= start)
+                    && *cell != NOTE
+                {
+                    *cell = LONG_NOTE_BODY;
+                }
+            }
+
+            let mut line = String::from(if row == row_count - 1 - BOTTOM_ROWS {
+                ">"
+            } else {
+                "│"
+            });
+            line.extend(cells);
+            line.push('│');
+            for btp in beatmap.bpm_time_points.iter().filter(|v| in_row(v.offset)) {
+                line.push_str(&format!(" {} BPM", btp.bpm));
+            }
+            for etp in beatmap
+                .effect_time_points
+                .iter()
+                .filter(|v| in_row(v.offset))
+            {
+                line.push_str(&format!(" {}x", etp.velocity_multiplier));
+            }
+            lines.push(line);
+        }
+        lines.push(HELP.to_owned());
+        lines
+            .into_iter()
+            .map(|v| v.chars().take(width).collect())
+            .collect()
+    }
+
+    /// Returns whether to keep running.
+    fn handle_key(&mut self, key: KeyEvent, height: u16) -> bool {
+        let page = (height as i32 - 4).max(1) * self.ms_per_row;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Char(' ') => self.playing = !self.playing,
+            KeyCode::Up | KeyCode::Char('k') => self.time += self.ms_per_row,
+            KeyCode::Down | KeyCode::Char('j') => self.time -= self.ms_per_row,
+            KeyCode::PageUp => self.time += page,
+            KeyCode::PageDown => self.time -= page,
+            KeyCode::Home => self.seek_to_start(),
+            KeyCode::End => self.seek_to_end(),
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.ms_per_row = (self.ms_per_row / 2).max(MIN_MS_PER_ROW)
+            }
+            KeyCode::Char('-') => self.ms_per_row = (self.ms_per_row * 2).min(MAX_MS_PER_ROW),
+            KeyCode::Right | KeyCode::Tab | KeyCode::Char('l') => {
+                self.index = (self.index + 1) % self.package.beatmaps.len().max(1)
+            }
+            KeyCode::Left | KeyCode::BackTab | KeyCode::Char('h') => {
+                let count = self.package.beatmaps.len().max(1);
+                self.index = (self.index + count - 1) % count
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        for (row, line) in self.frame(width, height).iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(0, row as u16),
+                Print(line),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        queue!(out, terminal::Clear(ClearType::FromCursorDown))?;
+        out.flush()
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut last = Instant::now();
+        loop {
+            if self.playing {
+                // Keep the remainder for the next frame.
+                let elapsed = last.elapsed().as_millis() as u64;
+                self.time += elapsed as i32;
+                last += Duration::from_millis(elapsed);
+            }
+            self.draw(out)?;
+            let timeout = match self.playing {
+                true => Duration::from_millis(16),
+                false => Duration::from_millis(500),
+            };
+            if !event::poll(timeout)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Release && !self.handle_key(key, terminal::size()?.1) {
+                    return Ok(());
+                }
+                last = Instant::now();
+            }
+        }
+    }
+
+    /// Take over the terminal until the user quits.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut out = io::stdout();
+        let _guard = TerminalGuard::new(&mut out)?;
+        self.event_loop(&mut out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::univsrg::types::{BpmTimePoint, Object, TimeSignature};
+
+    #[test]
+    fn viewer_frame() {
+        let mut beatmap = Beatmap::new();
+        beatmap.version = Some("Hard".to_owned());
+        beatmap.column_count = Some(4);
+        beatmap.bpm_time_points.push(BpmTimePoint {
+            offset: 0,
+            bpm: 120.0,
+            time_signature: TimeSignature::default(),
+        });
+        beatmap.objects = vec![
+            Object::Note {
+                column: 0,
+                offset: 0,
+            },
+            Object::LongNote {
+                column: 2,
+                offset: 100,
+                end_offset: 200,
+            },
+        ];
+        let mut package = Package::new();
+        package.beatmaps.push(beatmap);
+
+        let mut viewer = Viewer::new(&package);
+        viewer.ms_per_row = 50;
+        // 2 status lines, 8 rows and the help line.
+        let frame = viewer.frame(80, 11);
+        assert_eq!(frame.len(), 11);
+        assert!(frame[0].ends_with("Hard"));
+        // The judgement line is at 0 ms with a measure line.
+        assert_eq!(frame[7], ">▆▆▆─────────│ 120 BPM");
+        assert_eq!(frame[5], "│      ▆▆▆   │");
+        assert_eq!(frame[4], "│       █    │");
+        assert_eq!(frame[3], "│       █    │");
+
+        viewer.handle_key(KeyEvent::from(KeyCode::Char('+')), 11);
+        assert_eq!(viewer.ms_per_row, 25);
+        assert!(!viewer.handle_key(KeyEvent::from(KeyCode::Char('q')), 11));
+    }
+}